@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use failure::Error;
+
+use super::suggest;
+use crate::juju::Application;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = include_str!("../../tests/charms/keystone/config.yaml");
+
+    #[test]
+    fn it_parses_a_config_schema() {
+        let schema = ConfigSchema::parse(SCHEMA).unwrap();
+        assert_eq!(schema.options["token-expiration"].kind, OptionType::Int);
+    }
+
+    #[test]
+    fn it_reports_unknown_options() {
+        let schema = ConfigSchema::parse(SCHEMA).unwrap();
+        let application = Application::parse(
+            r#"charm: cs:keystone
+options:
+  regoin: RegionTwo
+  openstack-origin: cloud:bionic-train"#,
+        )
+        .unwrap();
+        let issues = schema.verify("keystone", &application);
+        assert_eq!(
+            issues
+                .iter()
+                .map(|issue| issue.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "keystone / openstack-origin is not an option of keystone",
+                "keystone / regoin is not an option of keystone, did you mean region?",
+            ]
+        );
+    }
+
+    #[test]
+    fn it_reports_mismatched_types() {
+        let schema = ConfigSchema::parse(SCHEMA).unwrap();
+        let application = Application::parse(
+            r#"charm: cs:keystone
+options:
+  region: RegionTwo
+  worker-multiplier: 2
+  token-expiration: 1.5
+  use-syslog: 'true'"#,
+        )
+        .unwrap();
+        let issues = schema.verify("keystone", &application);
+        assert_eq!(
+            issues
+                .iter()
+                .map(|issue| issue.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "keystone / token-expiration should be an int, found Number(Float(1.5))",
+                "keystone / use-syslog should be a boolean, found String(\"true\")",
+            ]
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OptionType {
+    String,
+    Int,
+    Float,
+    Boolean,
+}
+
+impl OptionType {
+    pub fn accepts(self, value: &serde_yaml::Value) -> bool {
+        match self {
+            OptionType::String => value.is_string(),
+            OptionType::Int => value.is_i64() || value.is_u64(),
+            OptionType::Float => value.is_number(),
+            OptionType::Boolean => value.is_bool(),
+        }
+    }
+}
+
+impl fmt::Display for OptionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionType::String => write!(f, "a string"),
+            OptionType::Int => write!(f, "an int"),
+            OptionType::Float => write!(f, "a float"),
+            OptionType::Boolean => write!(f, "a boolean"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct OptionSchema {
+    #[serde(rename = "type")]
+    pub kind: OptionType,
+    #[serde(default)]
+    pub default: Option<serde_yaml::Value>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// The options a charm declares in its `config.yaml`.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ConfigSchema {
+    #[serde(default)]
+    pub options: BTreeMap<String, OptionSchema>,
+}
+
+/// A bundle option that doesn't match its charm's config schema.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionIssue {
+    Unknown {
+        application: String,
+        charm: String,
+        option: String,
+        suggestion: Option<String>,
+    },
+    WrongType {
+        application: String,
+        option: String,
+        expected: OptionType,
+        found: serde_yaml::Value,
+    },
+}
+
+impl fmt::Display for OptionIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionIssue::Unknown {
+                application,
+                charm,
+                option,
+                suggestion,
+            } => {
+                write!(
+                    f,
+                    "{} / {} is not an option of {}",
+                    application, option, charm
+                )?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean {}?", suggestion)?;
+                }
+                Ok(())
+            }
+            OptionIssue::WrongType {
+                application,
+                option,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} / {} should be {}, found {:?}",
+                application, option, expected, found
+            ),
+        }
+    }
+}
+
+impl ConfigSchema {
+    pub fn parse(input: &str) -> Result<ConfigSchema, Error> {
+        Ok(serde_yaml::from_str(input)?)
+    }
+
+    pub fn verify(&self, name: &str, application: &Application) -> Vec<OptionIssue> {
+        let mut issues = vec![];
+        for (option, value) in application.options() {
+            match self.options.get(option) {
+                Some(schema) => {
+                    if !value.is_null() && !schema.kind.accepts(value) {
+                        issues.push(OptionIssue::WrongType {
+                            application: name.to_string(),
+                            option: option.clone(),
+                            expected: schema.kind,
+                            found: value.clone(),
+                        });
+                    }
+                }
+                None => issues.push(OptionIssue::Unknown {
+                    application: name.to_string(),
+                    charm: application.charm_name().to_string(),
+                    option: option.clone(),
+                    suggestion: suggest(option, self.options.keys().map(|k| k.as_str())),
+                }),
+            }
+        }
+        issues
+    }
+}
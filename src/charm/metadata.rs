@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use failure::Error;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &str = include_str!("../../tests/charms/percona-cluster/metadata.yaml");
+
+    #[test]
+    fn it_parses_metadata() {
+        let metadata = Metadata::parse(METADATA).unwrap();
+        assert_eq!(metadata.name, "percona-cluster");
+        assert_eq!(metadata.provides["db"].interface, "mysql");
+        assert_eq!(metadata.provides["shared-db"].interface, "mysql-shared");
+        assert_eq!(metadata.peers["cluster"].interface, "percona-cluster");
+    }
+
+    #[test]
+    fn it_provides_juju_info_implicitly() {
+        let metadata = Metadata::parse(METADATA).unwrap();
+        let endpoint = metadata.endpoint("juju-info").unwrap();
+        assert_eq!(endpoint.role, Role::Provider);
+        assert_eq!(endpoint.interface, "juju-info");
+    }
+}
+
+/// The role an endpoint plays in a relation.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Role {
+    Provider,
+    Requirer,
+    Peer,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Role::Provider => write!(f, "provides"),
+            Role::Requirer => write!(f, "requires"),
+            Role::Peer => write!(f, "peers"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RelationSpec {
+    Interface(String),
+    Full {
+        interface: String,
+        #[serde(default)]
+        limit: Option<usize>,
+        #[serde(default)]
+        scope: Option<String>,
+    },
+}
+
+/// An endpoint declared under `provides`, `requires` or `peers`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(from = "RelationSpec")]
+pub struct RelationSchema {
+    pub interface: String,
+    pub limit: Option<usize>,
+    pub scope: Option<String>,
+}
+
+impl From<RelationSpec> for RelationSchema {
+    fn from(spec: RelationSpec) -> RelationSchema {
+        match spec {
+            RelationSpec::Interface(interface) => RelationSchema {
+                interface,
+                limit: None,
+                scope: None,
+            },
+            RelationSpec::Full {
+                interface,
+                limit,
+                scope,
+            } => RelationSchema {
+                interface,
+                limit,
+                scope,
+            },
+        }
+    }
+}
+
+/// A named endpoint of a charm, with the role it was declared in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Endpoint<'a> {
+    pub name: &'a str,
+    pub role: Role,
+    pub interface: &'a str,
+    pub limit: Option<usize>,
+}
+
+/// The parts of a charm's `metadata.yaml` needed to check relations.
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Metadata {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub subordinate: bool,
+    #[serde(default)]
+    pub provides: BTreeMap<String, RelationSchema>,
+    #[serde(default)]
+    pub requires: BTreeMap<String, RelationSchema>,
+    #[serde(default)]
+    pub peers: BTreeMap<String, RelationSchema>,
+}
+
+const JUJU_INFO: &str = "juju-info";
+
+impl Metadata {
+    pub fn parse(input: &str) -> Result<Metadata, Error> {
+        Ok(serde_yaml::from_str(input)?)
+    }
+
+    /// Every endpoint of the charm, including the `juju-info` endpoint that
+    /// Juju provides for all charms.
+    pub fn endpoints(&self) -> Vec<Endpoint<'_>> {
+        let roles = vec![
+            (Role::Provider, &self.provides),
+            (Role::Requirer, &self.requires),
+            (Role::Peer, &self.peers),
+        ];
+        let mut endpoints: Vec<_> = roles
+            .into_iter()
+            .flat_map(|(role, relations)| {
+                relations.iter().map(move |(name, schema)| Endpoint {
+                    name,
+                    role,
+                    interface: &schema.interface,
+                    limit: schema.limit,
+                })
+            })
+            .collect();
+        if !endpoints.iter().any(|e| e.name == JUJU_INFO) {
+            endpoints.push(Endpoint {
+                name: JUJU_INFO,
+                role: Role::Provider,
+                interface: JUJU_INFO,
+                limit: None,
+            });
+        }
+        endpoints
+    }
+
+    pub fn endpoint(&self, name: &str) -> Option<Endpoint<'_>> {
+        self.endpoints().into_iter().find(|e| e.name == name)
+    }
+}
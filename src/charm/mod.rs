@@ -0,0 +1,166 @@
+//! Charm schemas, loaded from local charm directories or a vendored cache,
+//! used to validate a bundle against the charms it deploys.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use failure::Error;
+use log::debug;
+
+mod config;
+mod metadata;
+mod relation;
+
+pub use config::{ConfigSchema, OptionIssue, OptionSchema, OptionType};
+pub use metadata::{Endpoint, Metadata, RelationSchema, Role};
+pub use relation::RelationIssue;
+
+use crate::juju::Bundle;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_suggests_a_close_match() {
+        let candidates = ["worker-multiplier", "region", "vip"];
+        assert_eq!(
+            suggest("worker-multiplyer", candidates.iter().cloned()),
+            Some("worker-multiplier".to_string())
+        );
+        assert_eq!(
+            suggest("regoin", candidates.iter().cloned()),
+            Some("region".to_string())
+        );
+        assert_eq!(
+            suggest("openstack-origin", candidates.iter().cloned()),
+            None
+        );
+    }
+
+    #[test]
+    fn it_finds_a_schema_in_a_cache() {
+        let store = SchemaStore::new(vec![
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/charms")
+        ]);
+        assert!(store.config_schema("keystone").unwrap().is_some());
+        assert!(store.config_schema("glance").unwrap().is_none());
+    }
+
+    #[test]
+    fn it_finds_a_schema_in_a_charm_directory() {
+        let store = SchemaStore::new(vec![
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/charms/keystone")
+        ]);
+        assert!(store.config_schema("keystone").unwrap().is_some());
+    }
+}
+
+/// Locates charm schema files.
+///
+/// Each path is either a charm directory, named after the charm it holds, or
+/// a cache directory containing one such directory per charm.
+#[derive(Debug, Default)]
+pub struct SchemaStore {
+    paths: Vec<PathBuf>,
+}
+
+impl SchemaStore {
+    pub fn new(paths: Vec<PathBuf>) -> SchemaStore {
+        SchemaStore { paths }
+    }
+
+    pub fn config_schema(&self, charm: &str) -> Result<Option<ConfigSchema>, Error> {
+        match self.find(charm, "config.yaml") {
+            Some(path) => Ok(Some(ConfigSchema::parse(&fs::read_to_string(path)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn metadata(&self, charm: &str) -> Result<Option<Metadata>, Error> {
+        match self.find(charm, "metadata.yaml") {
+            Some(path) => Ok(Some(Metadata::parse(&fs::read_to_string(path)?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks every relation's endpoints against the metadata of the charms
+    /// on both sides, resolving relations that don't name an endpoint.
+    /// Relations involving a charm without metadata available are skipped.
+    pub fn verify_relations(&self, bundle: &Bundle) -> Result<Vec<RelationIssue>, Error> {
+        let mut metadata = BTreeMap::new();
+        for (name, application) in &bundle.applications {
+            match self.metadata(application.charm_name())? {
+                Some(m) => {
+                    metadata.insert(name.clone(), m);
+                }
+                None => debug!("No metadata found for {}", application.charm_name()),
+            }
+        }
+        Ok(relation::verify(bundle, &metadata))
+    }
+
+    /// Checks every application's options against its charm's config schema.
+    /// Applications whose charm has no schema available are skipped.
+    pub fn verify_options(&self, bundle: &Bundle) -> Result<Vec<OptionIssue>, Error> {
+        let mut names: Vec<_> = bundle.applications.keys().collect();
+        names.sort();
+        let mut issues = vec![];
+        for name in names {
+            let application = &bundle.applications[name];
+            match self.config_schema(application.charm_name())? {
+                Some(schema) => issues.append(&mut schema.verify(name, application)),
+                None => debug!("No config schema found for {}", application.charm_name()),
+            }
+        }
+        Ok(issues)
+    }
+
+    fn find(&self, charm: &str, file: &str) -> Option<PathBuf> {
+        for path in &self.paths {
+            let cached = path.join(charm).join(file);
+            if cached.is_file() {
+                return Some(cached);
+            }
+            if path.file_name().map(|n| n == charm).unwrap_or(false) && path.join(file).is_file() {
+                return Some(path.join(file));
+            }
+        }
+        None
+    }
+}
+
+/// The candidate closest to `name`, if any is close enough to be a likely
+/// typo.
+pub(crate) fn suggest<'a, I>(name: &str, candidates: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let limit = (name.len() / 3).max(2);
+    candidates
+        .into_iter()
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(d, _)| *d <= limit)
+        .min()
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Levenshtein distance between two strings.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current.push(
+                (previous[j] + cost)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::metadata::{Endpoint, Metadata, Role};
+use super::suggest;
+use crate::juju::{Bundle, RelationEndpoint};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> BTreeMap<String, Metadata> {
+        let mut charms = BTreeMap::new();
+        for (application, charm) in &[
+            ("keystone", "keystone"),
+            ("mysql", "percona-cluster"),
+            ("glance", "glance"),
+            ("keystone-hacluster", "hacluster"),
+            ("glance-hacluster", "hacluster"),
+        ] {
+            let path = format!(
+                "{}/tests/charms/{}/metadata.yaml",
+                env!("CARGO_MANIFEST_DIR"),
+                charm
+            );
+            let metadata = Metadata::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+            charms.insert(application.to_string(), metadata);
+        }
+        charms
+    }
+
+    fn issues(relations: &str) -> Vec<String> {
+        let bundle = Bundle::load(&format!(
+            r#"
+applications:
+  keystone:
+    charm: cs:keystone
+  mysql:
+    charm: cs:percona-cluster
+  glance:
+    charm: cs:glance
+  keystone-hacluster:
+    charm: cs:hacluster
+  glance-hacluster:
+    charm: cs:hacluster
+relations:
+{}"#,
+            relations
+        ))
+        .unwrap();
+        verify(&bundle, &metadata())
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn it_accepts_valid_relations() {
+        let found = issues(
+            r#"- ['keystone:shared-db', 'mysql:shared-db']
+- ['glance', 'keystone']
+- ['keystone', 'keystone-hacluster']
+- ['glance-hacluster:ha', 'glance']"#,
+        );
+        assert!(found.is_empty(), "{:?}", found);
+    }
+
+    #[test]
+    fn it_reports_unknown_endpoints() {
+        assert_eq!(
+            issues("- ['keystone:shared_db', 'mysql:shared-db']"),
+            vec!["keystone:shared_db is not an endpoint of keystone, did you mean shared-db?"]
+        );
+    }
+
+    #[test]
+    fn it_reports_incompatible_endpoints() {
+        assert_eq!(
+            issues("- ['keystone:shared-db', 'mysql:db']"),
+            vec!["keystone:shared-db (requires mysql-shared) can't relate to mysql:db (provides mysql)"]
+        );
+        assert_eq!(
+            issues("- ['keystone:ha', 'mysql:ha']"),
+            vec!["keystone:ha (requires hacluster) can't relate to mysql:ha (requires hacluster)"]
+        );
+    }
+
+    #[test]
+    fn it_reports_unresolvable_relations() {
+        assert_eq!(
+            issues("- ['glance', 'mysql:db']"),
+            vec!["glance and mysql:db have no matching endpoints"]
+        );
+    }
+
+    #[test]
+    fn it_enforces_limits() {
+        assert_eq!(
+            issues(
+                r#"- ['keystone:ha', 'keystone-hacluster:ha']
+- ['keystone:ha', 'glance-hacluster:ha']"#
+            ),
+            vec!["keystone:ha is in 2 relations, but is limited to 1"]
+        );
+    }
+}
+
+/// A bundle relation that doesn't match the charms' metadata.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RelationIssue {
+    UnknownEndpoint {
+        end: RelationEndpoint,
+        charm: String,
+        suggestion: Option<String>,
+    },
+    Incompatible {
+        ends: [RelationEndpoint; 2],
+        details: [String; 2],
+    },
+    Unresolved {
+        ends: [RelationEndpoint; 2],
+        candidates: Vec<[RelationEndpoint; 2]>,
+    },
+    LimitExceeded {
+        end: RelationEndpoint,
+        limit: usize,
+        count: usize,
+    },
+}
+
+impl fmt::Display for RelationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RelationIssue::UnknownEndpoint {
+                end,
+                charm,
+                suggestion,
+            } => {
+                write!(f, "{} is not an endpoint of {}", end, charm)?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean {}?", suggestion)?;
+                }
+                Ok(())
+            }
+            RelationIssue::Incompatible { ends, details } => write!(
+                f,
+                "{} ({}) can't relate to {} ({})",
+                ends[0], details[0], ends[1], details[1]
+            ),
+            RelationIssue::Unresolved { ends, candidates } => {
+                if candidates.is_empty() {
+                    write!(f, "{} and {} have no matching endpoints", ends[0], ends[1])
+                } else {
+                    let candidates: Vec<_> = candidates
+                        .iter()
+                        .map(|[a, b]| format!("{} and {}", a, b))
+                        .collect();
+                    write!(
+                        f,
+                        "{} and {} is ambiguous, could be {}",
+                        ends[0],
+                        ends[1],
+                        candidates.join(" or ")
+                    )
+                }
+            }
+            RelationIssue::LimitExceeded { end, limit, count } => write!(
+                f,
+                "{} is in {} relations, but is limited to {}",
+                end, count, limit
+            ),
+        }
+    }
+}
+
+fn compatible(a: &Endpoint, b: &Endpoint) -> bool {
+    a.interface == b.interface
+        && matches!(
+            (a.role, b.role),
+            (Role::Provider, Role::Requirer) | (Role::Requirer, Role::Provider)
+        )
+}
+
+/// Checks every relation in `bundle` against the metadata of the charms on
+/// each side, keyed by application name. Relations involving an application
+/// without metadata are skipped.
+pub(crate) fn verify(bundle: &Bundle, metadata: &BTreeMap<String, Metadata>) -> Vec<RelationIssue> {
+    let mut issues = vec![];
+    let mut counts: BTreeMap<RelationEndpoint, (usize, Option<usize>)> = BTreeMap::new();
+    for ends in bundle.relation_endpoints() {
+        let charms = match (
+            metadata.get(&ends[0].application),
+            metadata.get(&ends[1].application),
+        ) {
+            (Some(a), Some(b)) => [a, b],
+            _ => continue,
+        };
+        let mut known = true;
+        for (end, charm) in ends.iter().zip(charms.iter()) {
+            if let Some(endpoint) = &end.endpoint {
+                if charm.endpoint(endpoint).is_none() {
+                    known = false;
+                    let endpoints = charm.endpoints();
+                    issues.push(RelationIssue::UnknownEndpoint {
+                        end: end.clone(),
+                        charm: bundle.applications[&end.application]
+                            .charm_name()
+                            .to_string(),
+                        suggestion: suggest(endpoint, endpoints.iter().map(|e| e.name)),
+                    });
+                }
+            }
+        }
+        if !known {
+            continue;
+        }
+        let candidates = |index: usize| -> Vec<Endpoint> {
+            charms[index]
+                .endpoints()
+                .into_iter()
+                .filter(|e| {
+                    ends[index]
+                        .endpoint
+                        .as_deref()
+                        .map(|name| name == e.name)
+                        .unwrap_or(true)
+                })
+                .collect()
+        };
+        let (left, right) = (candidates(0), candidates(1));
+        let mut matches: Vec<(&Endpoint, &Endpoint)> = left
+            .iter()
+            .flat_map(|a| right.iter().map(move |b| (a, b)))
+            .filter(|(a, b)| compatible(a, b))
+            .collect();
+        if matches.len() > 1 {
+            // Juju prefers an explicit interface over the implicit juju-info.
+            let explicit: Vec<_> = matches
+                .iter()
+                .cloned()
+                .filter(|(a, _)| a.interface != "juju-info")
+                .collect();
+            if explicit.len() == 1 {
+                matches = explicit;
+            }
+        }
+        match matches.as_slice() {
+            [(a, b)] => {
+                for (end, endpoint) in ends.iter().zip(&[a, b]) {
+                    let count = counts
+                        .entry(RelationEndpoint::new(&end.application, Some(endpoint.name)))
+                        .or_insert((0, endpoint.limit));
+                    count.0 += 1;
+                }
+            }
+            [] if ends.iter().all(|end| end.endpoint.is_some()) => {
+                let (a, b) = (&left[0], &right[0]);
+                issues.push(RelationIssue::Incompatible {
+                    ends: ends.clone(),
+                    details: [
+                        format!("{} {}", a.role, a.interface),
+                        format!("{} {}", b.role, b.interface),
+                    ],
+                });
+            }
+            matches => issues.push(RelationIssue::Unresolved {
+                candidates: matches
+                    .iter()
+                    .map(|(a, b)| {
+                        [
+                            RelationEndpoint::new(&ends[0].application, Some(a.name)),
+                            RelationEndpoint::new(&ends[1].application, Some(b.name)),
+                        ]
+                    })
+                    .collect(),
+                ends,
+            }),
+        }
+    }
+    for (end, (count, limit)) in counts {
+        if let Some(limit) = limit {
+            if count > limit {
+                issues.push(RelationIssue::LimitExceeded { end, limit, count });
+            }
+        }
+    }
+    issues
+}
@@ -0,0 +1,50 @@
+// The custom derive for Fail derives an impl of both Fail and Display.
+// We don't do any other magic like creating new types.
+
+/// The ways linting a bundle can fail.
+///
+/// `LintFailure` means the lint ran and found problems; every other variant
+/// means it couldn't run.
+#[derive(Debug, Fail)]
+pub enum JujuLintError {
+    #[fail(display = "Bundle failed lint")]
+    LintFailure,
+    /// The bundle, an overlay or a status couldn't be read.
+    #[fail(display = "Failed to read {}: {}", path, reason)]
+    BundleIo { path: String, reason: String },
+    /// The bundle, an overlay or a status isn't valid, including the
+    /// bundle's includes.
+    #[fail(display = "Failed to parse {}: {}", path, reason)]
+    BundleParse { path: String, reason: String },
+    /// `fmt` can't format a valid bundle without changing it, e.g. because
+    /// it uses anchors.
+    #[fail(display = "Can't format {}: {}", path, reason)]
+    BundleFormat { path: String, reason: String },
+    /// The rule repository couldn't be reached.
+    #[fail(display = "Failed to fetch the rules from {}: {}", source, reason)]
+    RuleFetchNetwork { source: String, reason: String },
+    /// The rule repository refused the credentials, or asked for some.
+    #[fail(
+        display = "Not authorized to fetch the rules from {}: {}",
+        source, reason
+    )]
+    RuleFetchAuth { source: String, reason: String },
+    /// The branch or tag given after `#` doesn't exist in the repository.
+    #[fail(display = "{} has no branch or tag {}", source, reference)]
+    RuleRefNotFound { source: String, reference: String },
+    /// A local rule directory doesn't exist or isn't a directory.
+    #[fail(display = "The rule directory {} doesn't exist", path)]
+    RuleDirMissing { path: String },
+    /// The rule cache or a rule directory couldn't be read or written.
+    #[fail(display = "Failed to read or write {}: {}", path, reason)]
+    RuleIo { path: String, reason: String },
+    /// A rule file couldn't be read or isn't valid.
+    #[fail(display = "Failed to load the rules in {}: {}", path, reason)]
+    RuleParse { path: String, reason: String },
+    /// Two checks have the same id, e.g. two rules with the same name.
+    #[fail(display = "More than one check has the id {}", id)]
+    DuplicateCheck { id: String },
+    /// Running the `juju` command failed.
+    #[fail(display = "Failed to run {}: {}", command, reason)]
+    JujuCli { command: String, reason: String },
+}
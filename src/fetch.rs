@@ -1,48 +1,171 @@
 use crate::rule::Rule;
+use crate::JujuLintError;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use failure::Error;
-use git2::Repository;
+use git2::build::RepoBuilder;
+use git2::{ErrorClass, ErrorCode};
 use log::{debug, trace};
-use xdg;
 
-pub fn load(path: &str) -> Result<PathBuf, Error> {
+/// Whether `path` names a git repository rather than a local directory.
+fn is_remote(path: &str) -> bool {
+    path.contains("://") || path.starts_with("git@")
+}
+
+/// Finds the rules at `path`: a local directory, or a git repository that
+/// is cloned into the cache. A repository can be followed by `#` and a
+/// branch or tag to check out.
+pub fn load(path: &str) -> Result<PathBuf, JujuLintError> {
     let p = PathBuf::from(path);
     if p.exists() {
         debug!("Using local Rule path: {}", path);
         return Ok(p);
     }
-    trace!("About to download {}", path);
-    let xdg_dirs = xdg::BaseDirectories::with_prefix("bundle-lint")?;
-    let local_path = xdg_dirs.place_cache_file("juju_lint_base")?;
+    if !is_remote(path) {
+        return Err(JujuLintError::RuleDirMissing {
+            path: path.to_string(),
+        });
+    }
+    let (url, reference) = match path.rfind('#') {
+        Some(index) => (&path[..index], Some(&path[index + 1..])),
+        None => (path, None),
+    };
+    let network = |reason: String| JujuLintError::RuleFetchNetwork {
+        source: url.to_string(),
+        reason,
+    };
+    trace!("About to download {}", url);
+    let xdg_dirs =
+        xdg::BaseDirectories::with_prefix("bundle-lint").map_err(|e| JujuLintError::RuleIo {
+            path: "the cache directory".to_string(),
+            reason: e.to_string(),
+        })?;
+    let local_path =
+        xdg_dirs
+            .place_cache_file("juju_lint_base")
+            .map_err(|e| JujuLintError::RuleIo {
+                path: xdg_dirs.get_cache_home().display().to_string(),
+                reason: e.to_string(),
+            })?;
     debug!(
         "Trying to store rule configuration in {}",
         local_path.display()
     );
     if local_path.exists() {
-        fs::remove_dir_all(&local_path)?;
+        fs::remove_dir_all(&local_path).map_err(|e| JujuLintError::RuleIo {
+            path: local_path.display().to_string(),
+            reason: e.to_string(),
+        })?;
     }
-    debug!("Cloning {} to {}", path, local_path.display());
-    Repository::clone(path, &local_path)?;
+    debug!("Cloning {} to {}", url, local_path.display());
+    let mut builder = RepoBuilder::new();
+    if let Some(reference) = reference {
+        builder.branch(reference);
+    }
+    builder.clone(url, &local_path).map_err(|e| {
+        if e.code() == ErrorCode::Auth || e.message().contains("authentication") {
+            JujuLintError::RuleFetchAuth {
+                source: url.to_string(),
+                reason: e.message().to_string(),
+            }
+        } else if let (Some(reference), ErrorClass::Reference) = (reference, e.class()) {
+            JujuLintError::RuleRefNotFound {
+                source: url.to_string(),
+                reference: reference.to_string(),
+            }
+        } else {
+            network(e.message().to_string())
+        }
+    })?;
     Ok(local_path)
 }
 
-pub fn import(path: &PathBuf) -> Result<Vec<Rule>, Error> {
+pub fn import(path: &Path) -> Result<Vec<Rule>, JujuLintError> {
+    let io = |e: std::io::Error| JujuLintError::RuleIo {
+        path: path.display().to_string(),
+        reason: e.to_string(),
+    };
+    if !path.is_dir() {
+        return Err(JujuLintError::RuleDirMissing {
+            path: path.display().to_string(),
+        });
+    }
     let mut rules = vec![];
-    for entry in fs::read_dir(path)? {
-        let entry = entry?;
-        let path = entry.path();
+    for entry in fs::read_dir(path).map_err(io)? {
+        let path = entry.map_err(io)?.path();
         if path.is_file() {
             if let Some(extension) = path.extension().map(|a| a.to_string_lossy()) {
                 if extension == "yaml" {
-                    let rule_yaml = fs::read_to_string(path)?;
+                    let parse = |reason: String| JujuLintError::RuleParse {
+                        path: path.display().to_string(),
+                        reason,
+                    };
+                    let rule_yaml = fs::read_to_string(&path).map_err(|e| parse(e.to_string()))?;
                     debug!("Loding rules from:\n{}", rule_yaml);
-                    let mut local_rules: Vec<Rule> = serde_yaml::from_str(&rule_yaml)?;
-                    rules.append(&mut local_rules);
+                    let local_rules: Vec<Rule> =
+                        serde_yaml::from_str(&rule_yaml).map_err(|e| parse(e.to_string()))?;
+                    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                    for (i, mut rule) in local_rules.into_iter().enumerate() {
+                        rule.source = Some(format!("{}#{}", file_name, i + 1));
+                        rules.push(rule);
+                    }
                 }
             }
         }
     }
     Ok(rules)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reports_a_missing_rule_directory() {
+        match load("/nonexistent/rules") {
+            Err(JujuLintError::RuleDirMissing { path }) => assert_eq!(path, "/nonexistent/rules"),
+            other => panic!("Expected a missing directory, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_reports_a_missing_branch() {
+        let dir = std::env::temp_dir().join(format!("bundle-lint-ref-{}", std::process::id()));
+        let repository = git2::Repository::init(dir.join("rules")).unwrap();
+        let signature = git2::Signature::now("bundle-lint", "bundle-lint@example.com").unwrap();
+        let tree = repository.index().unwrap().write_tree().unwrap();
+        let tree = repository.find_tree(tree).unwrap();
+        repository
+            .commit(Some("HEAD"), &signature, &signature, "Rules", &tree, &[])
+            .unwrap();
+        // Keep the clone out of the real cache.
+        std::env::set_var("XDG_CACHE_HOME", dir.join("cache"));
+        let url = format!("file://{}", dir.join("rules").display());
+        let result = load(&format!("{}#no-such-branch", url));
+        fs::remove_dir_all(&dir).unwrap();
+        match result {
+            Err(JujuLintError::RuleRefNotFound { source, reference }) => {
+                assert_eq!(source, url);
+                assert_eq!(reference, "no-such-branch");
+            }
+            other => panic!("Expected a missing branch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_names_the_broken_rule_file() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/rules/broken");
+        match import(&path) {
+            Err(JujuLintError::RuleParse { path, .. }) => assert!(path.ends_with("broken.yaml")),
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_identifies_unnamed_rules_by_file_and_position() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/rules/unnamed");
+        let rules = import(&path).unwrap();
+        let ids: Vec<_> = rules.iter().map(|rule| rule.id()).collect();
+        assert_eq!(ids, vec!["keystone.yaml#1", "keystone.yaml#2"]);
+    }
+}
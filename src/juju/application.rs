@@ -1,44 +1,301 @@
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use failure::Error;
 
+use super::overlay::ApplicationOverlay;
+use super::variables::{self, VariableIssue};
+use super::IncludeResolver;
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const APPLICATION: &'static str = r#"charm: cs:ubuntu-12
+    const APPLICATION: &str = r#"charm: cs:ubuntu-12
 num_units: 1
 to:
 - "0""#;
     #[test]
     fn it_parses_an_application() {
-        let application = Application::parse(&APPLICATION).unwrap();
+        let application = Application::parse(APPLICATION).unwrap();
         assert_eq!(application.charm, "cs:ubuntu-12");
     }
+
+    #[test]
+    fn it_finds_the_charm_name() {
+        let application = Application::parse(APPLICATION).unwrap();
+        assert_eq!(application.charm_name(), "ubuntu");
+        let application =
+            Application::parse("charm: cs:~openstack-charmers/bionic/ceph-osd-294").unwrap();
+        assert_eq!(application.charm_name(), "ceph-osd");
+        let application = Application::parse("charm: ./charms/ceph-osd").unwrap();
+        assert_eq!(application.charm_name(), "ceph-osd");
+        let application = Application::parse("charm: ch:mysql-innodb-cluster").unwrap();
+        assert_eq!(application.charm_name(), "mysql-innodb-cluster");
+    }
+
+    #[test]
+    fn it_finds_the_revision() {
+        let application = Application::parse(APPLICATION).unwrap();
+        assert_eq!(application.revision(), Some(12));
+        let application = Application::parse("charm: ch:ceph-osd\nrevision: 513").unwrap();
+        assert_eq!(application.revision(), Some(513));
+        let application = Application::parse("charm: ch:mysql-innodb-cluster").unwrap();
+        assert_eq!(application.revision(), None);
+    }
+
+    #[test]
+    fn it_falls_back_to_the_default_binding() {
+        let application = Application::parse(
+            r#"charm: cs:keystone
+bindings:
+  "": oam-space
+  public: public-api"#,
+        )
+        .unwrap();
+        assert_eq!(application.binding("public"), Some("public-api"));
+        assert_eq!(application.binding("admin"), Some("oam-space"));
+    }
+
+    #[test]
+    fn it_keeps_the_include_directive() {
+        let mut application = Application::parse(
+            r#"charm: cs:keystone
+options:
+  ssl_cert: include-file://certs/ca.pem"#,
+        )
+        .unwrap();
+        let base = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/includes");
+        application
+            .resolve_includes(&IncludeResolver::new(base))
+            .unwrap();
+        let values = application.option_values("ssl_cert");
+        assert_eq!(values.len(), 2);
+        assert_eq!(
+            application.raw_option("ssl_cert").unwrap(),
+            "include-file://certs/ca.pem"
+        );
+        assert_ne!(
+            application.option("ssl_cert").unwrap(),
+            "include-file://certs/ca.pem"
+        );
+    }
 }
 
 fn zero() -> usize {
     0
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Application {
     charm: String,
+    /// The charm revision, as Charmhub bundles give it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    revision: Option<u64>,
     #[serde(default = "zero")]
     num_units: usize,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     to: Vec<String>,
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     options: BTreeMap<String, serde_yaml::Value>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    bindings: BTreeMap<String, String>,
+    /// Content of options that were given as include directives, keyed by
+    /// option name.
+    #[serde(skip)]
+    included: BTreeMap<String, serde_yaml::Value>,
+    /// The variable each option was set to as `$name`, so that it is
+    /// substituted again when an overlay replaces the variable.
+    #[serde(skip)]
+    references: BTreeMap<String, String>,
 }
 
 impl Application {
     pub fn parse(input: &str) -> Result<Application, Error> {
-        Ok(serde_yaml::from_str(&input)?)
+        Ok(serde_yaml::from_str(input)?)
+    }
+
+    pub fn charm(&self) -> &str {
+        &self.charm
+    }
+
+    /// The name of the charm without its schema, owner, series or revision,
+    /// e.g. `ceph-osd` for `cs:~openstack-charmers/bionic/ceph-osd-294`.
+    pub fn charm_name(&self) -> &str {
+        let charm = self.charm.rsplit('/').next().unwrap_or_default();
+        let charm = charm.splitn(2, ':').last().unwrap_or_default();
+        match charm.rfind('-') {
+            Some(index)
+                if index + 1 < charm.len()
+                    && charm[index + 1..].chars().all(|c| c.is_ascii_digit()) =>
+            {
+                &charm[..index]
+            }
+            _ => charm,
+        }
+    }
+
+    /// The revision of the charm the bundle pins, from `revision` or the
+    /// end of a charm store URL, e.g. 294 for `cs:ceph-osd-294`.
+    pub fn revision(&self) -> Option<u64> {
+        if self.revision.is_some() {
+            return self.revision;
+        }
+        let charm = self.charm.rsplit('/').next().unwrap_or_default();
+        let (_, revision) = charm.rsplit_once('-')?;
+        revision.parse().ok()
+    }
+
+    pub fn num_units(&self) -> usize {
+        self.num_units
+    }
+
+    /// Placement directives for the units, in unit order, e.g. `0`,
+    /// `lxd:1` or `ceph-osd/2`.
+    pub fn placements(&self) -> &[String] {
+        &self.to
     }
 
+    /// Every option as it was written in the bundle.
+    pub fn options(&self) -> &BTreeMap<String, serde_yaml::Value> {
+        &self.options
+    }
+
+    /// The value of an option, with any include directive resolved.
     pub fn option(&self, option: &str) -> Option<&serde_yaml::Value> {
+        self.included
+            .get(option)
+            .or_else(|| self.options.get(option))
+    }
+
+    /// The value of an option as it was written in the bundle.
+    pub fn raw_option(&self, option: &str) -> Option<&serde_yaml::Value> {
         self.options.get(option)
     }
+
+    /// Every value an option can be matched on: the resolved content first,
+    /// followed by the directive if the option was included.
+    pub fn option_values(&self, option: &str) -> Vec<&serde_yaml::Value> {
+        self.included
+            .get(option)
+            .into_iter()
+            .chain(self.options.get(option))
+            .collect()
+    }
+
+    /// The space an endpoint is bound to, falling back to the application's
+    /// default binding.
+    pub fn binding(&self, endpoint: &str) -> Option<&str> {
+        self.bindings
+            .get(endpoint)
+            .or_else(|| self.bindings.get(""))
+            .map(|s| s.as_str())
+    }
+
+    /// The explicitly bound endpoints and their spaces. The default binding
+    /// has an empty endpoint name.
+    pub fn bindings(&self) -> &BTreeMap<String, String> {
+        &self.bindings
+    }
+
+    /// Replaces `$name` option values with the matching bundle variable,
+    /// recording each variable used and any that are undefined. Options
+    /// substituted before are substituted again, with the current value.
+    pub(crate) fn resolve_variables(
+        &mut self,
+        application: &str,
+        variables: &BTreeMap<String, serde_yaml::Value>,
+        used: &mut BTreeSet<String>,
+    ) -> Vec<VariableIssue> {
+        let mut issues = vec![];
+        for (option, value) in &mut self.options {
+            let name = match self.references.get(option) {
+                Some(name) => name.clone(),
+                None => match variables::reference(value) {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                },
+            };
+            self.references.insert(option.clone(), name.clone());
+            match variables.get(&name) {
+                Some(variable) => {
+                    *value = variable.clone();
+                    used.insert(name);
+                }
+                None => issues.push(VariableIssue::Undefined {
+                    application: application.to_string(),
+                    option: option.clone(),
+                    variable: name,
+                }),
+            }
+        }
+        issues
+    }
+
+    pub fn resolve_includes(&mut self, resolver: &IncludeResolver) -> Result<(), Error> {
+        for (name, value) in &self.options {
+            if let Some(content) = resolver.resolve(value)? {
+                self.included.insert(name.clone(), content);
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates an application added by an overlay.
+    pub(crate) fn from_overlay(
+        name: &str,
+        overlay: &ApplicationOverlay,
+        resolver: Option<&IncludeResolver>,
+    ) -> Result<Application, Error> {
+        let charm = match &overlay.charm {
+            Some(charm) => charm.clone(),
+            None => return Err(format_err!("The overlay adds {} without a charm", name)),
+        };
+        let mut application = Application {
+            charm,
+            revision: None,
+            num_units: 0,
+            to: vec![],
+            options: BTreeMap::new(),
+            bindings: BTreeMap::new(),
+            included: BTreeMap::new(),
+            references: BTreeMap::new(),
+        };
+        application.apply_overlay(overlay, resolver)?;
+        Ok(application)
+    }
+
+    /// Replaces what the overlay sets, resolving include directives in its
+    /// options with `resolver`. A `null` option is unset.
+    pub(crate) fn apply_overlay(
+        &mut self,
+        overlay: &ApplicationOverlay,
+        resolver: Option<&IncludeResolver>,
+    ) -> Result<(), Error> {
+        if let Some(charm) = &overlay.charm {
+            self.charm = charm.clone();
+        }
+        if let Some(num_units) = overlay.num_units {
+            self.num_units = num_units;
+        }
+        if let Some(to) = &overlay.to {
+            self.to = to.clone();
+        }
+        for (name, value) in &overlay.options {
+            self.included.remove(name);
+            self.references.remove(name);
+            if value.is_null() {
+                self.options.remove(name);
+                continue;
+            }
+            if let Some(resolver) = resolver {
+                if let Some(content) = resolver.resolve(value)? {
+                    self.included.insert(name.clone(), content);
+                }
+            }
+            self.options.insert(name.clone(), value.clone());
+        }
+        self.bindings.extend(overlay.bindings.clone());
+        Ok(())
+    }
 }
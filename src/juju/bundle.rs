@@ -1,24 +1,100 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use failure::Error;
 
-use super::{Application, Machine};
+use super::{
+    Application, BundleDiff, IncludeResolver, Machine, Overlay, RelationEndpoint, VariableIssue,
+};
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    const BUNDLE: &'static str = include_str!("../../tests/test_bundle.yaml");
+    const BUNDLE: &str = include_str!("../../tests/test_bundle.yaml");
+
+    #[test]
+    fn it_finds_the_machine_hosting_a_unit() {
+        let bundle = Bundle::load(
+            r#"
+applications:
+  ceph-osd:
+    charm: cs:ceph-osd
+    num_units: 2
+    to: ["0", "1"]
+  ceph-mon:
+    charm: cs:ceph-mon
+    num_units: 3
+    to: ["lxd:0", "lxd:ceph-osd/1", "new"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(bundle.unit_machine("ceph-mon", 0), Some("0"));
+        assert_eq!(bundle.unit_machine("ceph-mon", 1), Some("1"));
+        assert_eq!(bundle.unit_machine("ceph-mon", 2), None);
+        assert_eq!(bundle.unit_machine("ceph-osd", 2), None);
+    }
 
     #[test]
     fn it_parses_a_bundle() {
         let bundle = Bundle::load(BUNDLE).unwrap();
         assert_eq!(bundle.series.unwrap(), "bionic");
     }
+
+    const VARIABLES: &str = r#"
+variables:
+  worker_mult: 0.25
+  openstack_origin: cloud:bionic-train
+  unused_var: 3
+applications:
+  nova-cloud-controller:
+    charm: cs:nova-cloud-controller
+    options:
+      worker-multiplier: $worker_mult
+      openstack-origin: $openstack_origin
+  keystone:
+    charm: cs:keystone
+    options:
+      worker-multiplier: $worker_mult
+      region: $region
+"#;
+
+    #[test]
+    fn it_substitutes_variables() {
+        let bundle = Bundle::load(VARIABLES).unwrap();
+        let ncc = bundle.application("nova-cloud-controller").unwrap();
+        assert_eq!(
+            ncc.option("worker-multiplier").unwrap(),
+            &serde_yaml::Value::from(0.25)
+        );
+        assert_eq!(
+            ncc.option("openstack-origin").unwrap(),
+            "cloud:bionic-train"
+        );
+    }
+
+    #[test]
+    fn it_reports_undefined_and_unused_variables() {
+        let bundle = Bundle::load(VARIABLES).unwrap();
+        assert_eq!(
+            bundle.variable_issues(),
+            &[
+                VariableIssue::Undefined {
+                    application: "keystone".into(),
+                    option: "region".into(),
+                    variable: "region".into(),
+                },
+                VariableIssue::Unused {
+                    variable: "unused_var".into()
+                },
+            ]
+        );
+        let keystone = bundle.application("keystone").unwrap();
+        assert_eq!(keystone.option("region").unwrap(), "$region");
+    }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Bundle {
     pub series: Option<String>,
     pub applications: HashMap<String, Application>,
@@ -26,14 +102,135 @@ pub struct Bundle {
     pub machines: HashMap<String, Machine>,
     #[serde(default)]
     pub relations: Vec<[String; 2]>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, serde_yaml::Value>,
+    #[serde(skip)]
+    variable_issues: Vec<VariableIssue>,
 }
 
 impl Bundle {
     pub fn load(input_yaml: &str) -> Result<Bundle, Error> {
-        Ok(serde_yaml::from_str(&input_yaml)?)
+        let mut bundle: Bundle = serde_yaml::from_str(input_yaml)?;
+        bundle.resolve_variables();
+        Ok(bundle)
+    }
+
+    fn resolve_variables(&mut self) {
+        let mut issues = vec![];
+        let mut used = BTreeSet::new();
+        for (name, application) in &mut self.applications {
+            issues.append(&mut application.resolve_variables(name, &self.variables, &mut used));
+        }
+        issues.sort();
+        for variable in self.variables.keys() {
+            if !used.contains(variable) {
+                issues.push(VariableIssue::Unused {
+                    variable: variable.clone(),
+                });
+            }
+        }
+        self.variable_issues = issues;
+    }
+
+    /// Undefined and unused variables found while loading the bundle and
+    /// applying overlays.
+    pub fn variable_issues(&self) -> &[VariableIssue] {
+        &self.variable_issues
+    }
+
+    pub fn resolve_includes(&mut self, resolver: &IncludeResolver) -> Result<(), Error> {
+        for (name, application) in &mut self.applications {
+            // Kept as the cause, so that unreadable files can be told apart.
+            application.resolve_includes(resolver).map_err(|e| {
+                let message = format!("Failed to resolve includes for {}: {}", name, e);
+                e.context(message)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Merges an overlay into the bundle, as `juju deploy --overlay` would.
+    pub fn apply_overlay(&mut self, overlay: &Overlay) -> Result<(), Error> {
+        let resolver = overlay.base.clone().map(IncludeResolver::new);
+        for (name, application) in &overlay.applications {
+            match application {
+                None => {
+                    self.applications.remove(name);
+                    self.relations.retain(|relation| {
+                        !relation
+                            .iter()
+                            .any(|end| end.split(':').next() == Some(name.as_str()))
+                    });
+                }
+                Some(application) => match self.applications.get_mut(name) {
+                    Some(existing) => existing
+                        .apply_overlay(application, resolver.as_ref())
+                        .map_err(|e| {
+                            let message = format!("Failed to apply the overlay to {}: {}", name, e);
+                            e.context(message)
+                        })?,
+                    None => {
+                        let added =
+                            Application::from_overlay(name, application, resolver.as_ref())?;
+                        self.applications.insert(name.clone(), added);
+                    }
+                },
+            }
+        }
+        for (name, machine) in &overlay.machines {
+            match machine {
+                Some(machine) => {
+                    self.machines.insert(name.clone(), machine.clone());
+                }
+                None => {
+                    self.machines.remove(name);
+                }
+            }
+        }
+        self.relations.extend(overlay.relations.iter().cloned());
+        self.variables.extend(overlay.variables.clone());
+        self.resolve_variables();
+        Ok(())
     }
 
     pub fn application(&self, application_name: &str) -> Option<&Application> {
         self.applications.get(application_name)
     }
+
+    /// The bundle machine that hosts a unit, following container and unit
+    /// placements, e.g. `lxd:1` is hosted on machine `1`. `None` if the unit
+    /// has no placement or is placed on a new machine.
+    pub fn unit_machine(&self, application: &str, unit: usize) -> Option<&str> {
+        let mut placement = self.application(application)?.placements().get(unit)?;
+        // Guards against placements that refer to each other.
+        for _ in 0..self.applications.len() {
+            let target = placement.rsplit(':').next().unwrap_or_default();
+            if target == "new" {
+                return None;
+            }
+            match target.find('/') {
+                Some(index) => {
+                    let unit = target[index + 1..].parse::<usize>().ok()?;
+                    placement = self.application(&target[..index])?.placements().get(unit)?;
+                }
+                None => return Some(target),
+            }
+        }
+        None
+    }
+
+    /// The bundle's relations, with each end split into its application and
+    /// endpoint. Relations with an end that has no application are skipped.
+    pub fn relation_endpoints(&self) -> Vec<[RelationEndpoint; 2]> {
+        self.relations
+            .iter()
+            .filter_map(|[a, b]| Some([a.parse().ok()?, b.parse().ok()?]))
+            .collect()
+    }
+
+    /// How `other` differs from this bundle, ignoring the order of
+    /// relations and of the ends of each.
+    pub fn diff(&self, other: &Bundle) -> BundleDiff {
+        BundleDiff::between(self, other)
+    }
 }
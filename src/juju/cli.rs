@@ -0,0 +1,76 @@
+use log::debug;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::JujuLintError;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn juju() -> Juju {
+        Juju::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/juju/fake-juju"))
+    }
+
+    #[test]
+    fn it_returns_the_output() {
+        let output = juju()
+            .run(&["export-bundle", "--model", "ctrl:openstack"])
+            .unwrap();
+        assert!(output.contains("cs:keystone"));
+    }
+
+    #[test]
+    fn it_reports_the_exit_status_and_stderr() {
+        match juju().run(&["export-bundle", "--model", "ctrl:missing"]) {
+            Err(JujuLintError::JujuCli { reason, .. }) => {
+                assert!(reason.contains("exit status: 2"));
+                assert!(reason.contains("model \"ctrl:missing\" not found"));
+            }
+            other => panic!("Expected the command to fail, got {:?}", other),
+        }
+    }
+}
+
+/// Runs the `juju` command, or a stand-in for it.
+#[derive(Clone, Debug)]
+pub struct Juju {
+    bin: PathBuf,
+}
+
+impl Default for Juju {
+    fn default() -> Juju {
+        Juju::new(PathBuf::from("juju"))
+    }
+}
+
+impl Juju {
+    pub fn new(bin: PathBuf) -> Juju {
+        Juju { bin }
+    }
+
+    /// Runs `juju` with `args`, returning its stdout. Fails with the exit
+    /// status and stderr if the command does.
+    pub fn run(&self, args: &[&str]) -> Result<String, JujuLintError> {
+        let mut cmd = Command::new(&self.bin);
+        cmd.args(args);
+        let command = format!("{} {}", self.bin.display(), args.join(" "));
+        debug!("About to run {:?}", cmd);
+        let output = cmd.output().map_err(|e| JujuLintError::JujuCli {
+            command: command.clone(),
+            reason: e.to_string(),
+        })?;
+        debug!("Got output from juju: {:?}", output);
+        if !output.status.success() {
+            return Err(JujuLintError::JujuCli {
+                command,
+                reason: format!(
+                    "{}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
@@ -0,0 +1,569 @@
+use serde::Serialize;
+use serde_yaml::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::{pair_matches, Application, Bundle, Machine, RelationEndpoint};
+use crate::rule::display_value;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = r#"
+applications:
+  keystone:
+    charm: cs:keystone-309
+    num_units: 3
+    options:
+      debug: false
+      region: RegionOne
+  mysql:
+    charm: cs:percona-cluster
+    num_units: 3
+relations:
+  - [keystone:shared-db, mysql:shared-db]
+"#;
+
+    const NEW: &str = r#"
+applications:
+  keystone:
+    charm: cs:keystone-312
+    num_units: 1
+    options:
+      debug: true
+      worker-multiplier: 0.25
+  mysql-innodb-cluster:
+    charm: cs:mysql-innodb-cluster
+    num_units: 3
+relations:
+  - [mysql-innodb-cluster:db-router, keystone:shared-db]
+"#;
+
+    #[test]
+    fn it_finds_no_difference() {
+        let bundle = Bundle::load(OLD).unwrap();
+        assert!(bundle.diff(&bundle).is_empty());
+    }
+
+    #[test]
+    fn it_compares_included_options_by_content() {
+        let path =
+            std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/includes/bundle.yaml");
+        let bundle = super::super::Model::load_bundle(path).unwrap();
+        let exported = Bundle::load(
+            r#"
+applications:
+  keystone:
+    charm: cs:keystone
+    num_units: 1
+    options:
+      ssl_ca: LS0tLS1CRUdJTiBDRVJUSUZJQ0FURS0tLS0tCnRlc3QKLS0tLS1FTkQgQ0VSVElGSUNBVEUtLS0tLQo=
+      ssl_cert: |
+        -----BEGIN CERTIFICATE-----
+        test
+        -----END CERTIFICATE-----
+"#,
+        )
+        .unwrap();
+        assert!(bundle.diff(&exported).is_empty());
+    }
+
+    #[test]
+    fn it_pairs_relations_with_and_without_endpoints() {
+        let relations = |yaml: &str| {
+            let yaml = format!(
+                "applications: {{a: {{charm: a}}, b: {{charm: b}}, c: {{charm: c}}}}\nrelations: {}",
+                yaml
+            );
+            Bundle::load(&yaml).unwrap()
+        };
+        let names = |relations: &[[RelationEndpoint; 2]]| -> Vec<String> {
+            relations
+                .iter()
+                .map(|[a, b]| format!("{} {}", a, b))
+                .collect()
+        };
+        let bundle = relations("[[a, b], [c:x, a:y]]");
+        let exported = relations("[[a:db, b:db], [a:y, c:x]]");
+        assert!(bundle.diff(&exported).is_empty());
+        assert!(exported.diff(&bundle).is_empty());
+
+        let diff = relations("[[a:db, b:db], [a:cert, b:cert]]").diff(&relations("[[a, b]]"));
+        assert!(diff.added_relations.is_empty());
+        assert_eq!(names(&diff.removed_relations), vec!["a:db b:db"]);
+
+        let diff = relations("[[a, b]]").diff(&relations("[[a, b], [b, a], [a, c]]"));
+        assert_eq!(names(&diff.added_relations), vec!["a b", "a c"]);
+        assert!(diff.removed_relations.is_empty());
+    }
+
+    #[test]
+    fn it_diffs_bundles() {
+        let diff = Bundle::load(OLD).unwrap().diff(&Bundle::load(NEW).unwrap());
+        assert_eq!(
+            diff.added_applications["mysql-innodb-cluster"],
+            "cs:mysql-innodb-cluster"
+        );
+        assert!(diff.removed_applications.contains_key("mysql"));
+        let keystone = &diff.changed_applications["keystone"];
+        assert_eq!(keystone.revision, Some(Change::new(Some(309), Some(312))));
+        assert_eq!(keystone.num_units, Some(Change::new(3, 1)));
+        assert_eq!(keystone.options.len(), 3);
+        assert_eq!(keystone.options["region"].new, None);
+        assert_eq!(diff.added_relations.len(), 1);
+        assert_eq!(diff.removed_relations.len(), 1);
+        assert_eq!(
+            diff.to_string(),
+            r#"adds `mysql-innodb-cluster` (cs:mysql-innodb-cluster)
+removes `mysql` (cs:percona-cluster)
+changes the revision of `keystone` from 309 to 312
+changes the units of `keystone` from 3 to 1
+changes `keystone.debug` from false to true
+unsets `keystone.region`, which was "RegionOne"
+sets `keystone.worker-multiplier` to 0.25
+adds the relation `keystone:shared-db` <-> `mysql-innodb-cluster:db-router`
+removes the relation `keystone:shared-db` <-> `mysql:shared-db`
+"#
+        );
+    }
+
+    #[test]
+    fn it_ignores_relation_order() {
+        let old = Bundle::load(OLD).unwrap();
+        let mut new = old.clone();
+        new.relations = vec![["mysql:shared-db".into(), "keystone:shared-db".into()]];
+        assert!(old.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn it_ignores_placement_order() {
+        let old = Bundle::load(
+            r#"
+machines:
+  "0": {}
+  "1": {}
+applications:
+  ceph-osd:
+    charm: cs:ceph-osd
+    num_units: 2
+    to: ["0", "1"]
+"#,
+        )
+        .unwrap();
+        let new = Bundle::load(
+            r#"
+machines:
+  "1": {}
+  "0": {}
+applications:
+  ceph-osd:
+    charm: cs:ceph-osd
+    num_units: 2
+    to: ["1", "0"]
+"#,
+        )
+        .unwrap();
+        assert!(old.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn it_diffs_machines_placements_and_bindings() {
+        let old = Bundle::load(
+            r#"
+machines:
+  "0": {series: bionic}
+  "1": {}
+applications:
+  ceph-osd:
+    charm: cs:ceph-osd
+    num_units: 2
+    to: ["0", "1"]
+    bindings:
+      "": oam-space
+"#,
+        )
+        .unwrap();
+        let new = Bundle::load(
+            r#"
+machines:
+  "0": {series: focal}
+  "2": {}
+applications:
+  ceph-osd:
+    charm: cs:ceph-osd
+    num_units: 2
+    to: ["0", "2"]
+    bindings:
+      "": oam-space
+      cluster: ceph-cluster
+"#,
+        )
+        .unwrap();
+        let diff = old.diff(&new);
+        assert!(diff.added_machines.contains("2"));
+        assert!(diff.removed_machines.contains("1"));
+        assert_eq!(
+            diff.changes(),
+            vec![
+                "changes the placements of `ceph-osd` from [\"0\", \"1\"] to [\"0\", \"2\"]",
+                "sets the binding of `ceph-osd:cluster` to ceph-cluster",
+                "adds machine 2",
+                "removes machine 1",
+                "changes the series of machine 0 from bionic to focal",
+            ]
+        );
+    }
+}
+
+/// A value that differs between two bundles.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Change<T> {
+    pub old: T,
+    pub new: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    pub fn new(old: T, new: T) -> Change<T> {
+        Change { old, new }
+    }
+
+    /// The change from `old` to `new`, if they differ.
+    fn between(old: T, new: T) -> Option<Change<T>> {
+        if old == new {
+            None
+        } else {
+            Some(Change::new(old, new))
+        }
+    }
+}
+
+/// How an application that is in both bundles differs.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ApplicationDiff {
+    /// The charm, when it is a different charm rather than another
+    /// revision of it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charm: Option<Change<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<Change<Option<u64>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_units: Option<Change<usize>>,
+    /// The `to:` placements, sorted, when they differ in more than order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub placements: Option<Change<Vec<String>>>,
+    /// Options that were set, unset or changed, with `None` for unset.
+    /// Included options are compared by their content.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, Change<Option<Value>>>,
+    /// Endpoints bound to a different space, with `None` for unbound. The
+    /// default binding has an empty endpoint name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub bindings: BTreeMap<String, Change<Option<String>>>,
+}
+
+impl ApplicationDiff {
+    fn between(old: &Application, new: &Application) -> ApplicationDiff {
+        let names: BTreeSet<_> = old.options().keys().chain(new.options().keys()).collect();
+        let endpoints: BTreeSet<_> = old.bindings().keys().chain(new.bindings().keys()).collect();
+        ApplicationDiff {
+            charm: Change::between(old.charm_name(), new.charm_name())
+                .map(|_| Change::new(old.charm().to_string(), new.charm().to_string())),
+            revision: Change::between(old.revision(), new.revision()),
+            num_units: Change::between(old.num_units(), new.num_units()),
+            placements: Change::between(sorted(old.placements()), sorted(new.placements())),
+            options: names
+                .into_iter()
+                .filter_map(|name| {
+                    let change =
+                        Change::between(old.option(name).cloned(), new.option(name).cloned())?;
+                    Some((name.clone(), change))
+                })
+                .collect(),
+            bindings: endpoints
+                .into_iter()
+                .filter_map(|endpoint| {
+                    let change = Change::between(
+                        old.bindings().get(endpoint).cloned(),
+                        new.bindings().get(endpoint).cloned(),
+                    )?;
+                    Some((endpoint.clone(), change))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &ApplicationDiff::default()
+    }
+}
+
+/// How a machine that is in both bundles differs.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MachineDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series: Option<Change<Option<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub constraints: Option<Change<Option<String>>>,
+}
+
+impl MachineDiff {
+    fn between(old: &Machine, new: &Machine) -> MachineDiff {
+        let owned = |s: Option<&str>| s.map(|s| s.to_string());
+        MachineDiff {
+            series: Change::between(owned(old.series()), owned(new.series())),
+            constraints: Change::between(owned(old.constraints()), owned(new.constraints())),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &MachineDiff::default()
+    }
+}
+
+/// The differences between two bundles, e.g. the intended bundle and the
+/// one exported from a deployed model, keyed by application, option,
+/// machine and relation. The order of relations and placements is ignored.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct BundleDiff {
+    /// Applications only in the new bundle, with their charms.
+    pub added_applications: BTreeMap<String, String>,
+    /// Applications only in the old bundle, with their charms.
+    pub removed_applications: BTreeMap<String, String>,
+    pub changed_applications: BTreeMap<String, ApplicationDiff>,
+    pub added_machines: BTreeSet<String>,
+    pub removed_machines: BTreeSet<String>,
+    pub changed_machines: BTreeMap<String, MachineDiff>,
+    /// Relations only in the new bundle, in either order.
+    pub added_relations: Vec<[RelationEndpoint; 2]>,
+    /// Relations only in the old bundle, in either order.
+    pub removed_relations: Vec<[RelationEndpoint; 2]>,
+}
+
+impl BundleDiff {
+    pub(crate) fn between(old: &Bundle, new: &Bundle) -> BundleDiff {
+        let mut diff = BundleDiff::default();
+        let names: BTreeSet<_> = old
+            .applications
+            .keys()
+            .chain(new.applications.keys())
+            .collect();
+        for name in names {
+            match (old.application(name), new.application(name)) {
+                (Some(old), Some(new)) => {
+                    let changes = ApplicationDiff::between(old, new);
+                    if !changes.is_empty() {
+                        diff.changed_applications.insert(name.clone(), changes);
+                    }
+                }
+                (None, Some(new)) => {
+                    diff.added_applications
+                        .insert(name.clone(), new.charm().to_string());
+                }
+                (Some(old), None) => {
+                    diff.removed_applications
+                        .insert(name.clone(), old.charm().to_string());
+                }
+                (None, None) => {}
+            }
+        }
+        let machines: BTreeSet<_> = old.machines.keys().chain(new.machines.keys()).collect();
+        for id in machines {
+            match (old.machines.get(id), new.machines.get(id)) {
+                (Some(old), Some(new)) => {
+                    let changes = MachineDiff::between(old, new);
+                    if !changes.is_empty() {
+                        diff.changed_machines.insert(id.clone(), changes);
+                    }
+                }
+                (None, Some(_)) => {
+                    diff.added_machines.insert(id.clone());
+                }
+                (Some(_), None) => {
+                    diff.removed_machines.insert(id.clone());
+                }
+                (None, None) => {}
+            }
+        }
+        let old_relations = sorted_relations(old);
+        let new_relations = sorted_relations(new);
+        let (removed, added) = unpaired(&old_relations, &new_relations);
+        diff.removed_relations = removed;
+        diff.added_relations = added;
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_applications.is_empty()
+            && self.removed_applications.is_empty()
+            && self.changed_applications.is_empty()
+            && self.added_machines.is_empty()
+            && self.removed_machines.is_empty()
+            && self.changed_machines.is_empty()
+            && self.added_relations.is_empty()
+            && self.removed_relations.is_empty()
+    }
+}
+
+fn sorted(placements: &[String]) -> Vec<String> {
+    let mut placements = placements.to_vec();
+    placements.sort();
+    placements
+}
+
+/// The bundle's relations with the ends of each in order, sorted.
+fn sorted_relations(bundle: &Bundle) -> Vec<[RelationEndpoint; 2]> {
+    let mut relations: Vec<_> = bundle
+        .relation_endpoints()
+        .into_iter()
+        .map(|mut relation| {
+            relation.sort();
+            relation
+        })
+        .collect();
+    relations.sort();
+    relations
+}
+
+/// Pairs each relation in `old` with at most one in `new`, those that are
+/// equal first, then those that match where an endpoint is omitted, as in
+/// a bundle compared with `juju export-bundle`. Returns the relations left
+/// in `old`, which were removed, and in `new`, which were added.
+fn unpaired(
+    old: &[[RelationEndpoint; 2]],
+    new: &[[RelationEndpoint; 2]],
+) -> (Vec<[RelationEndpoint; 2]>, Vec<[RelationEndpoint; 2]>) {
+    let mut removed: Vec<_> = old.iter().collect();
+    let mut added: Vec<_> = new.iter().collect();
+    let equal = |a: &[RelationEndpoint; 2], b: &[RelationEndpoint; 2]| a == b;
+    for same in &[equal, pair_matches] {
+        removed.retain(
+            |relation| match added.iter().position(|o| same(relation, o)) {
+                Some(index) => {
+                    added.remove(index);
+                    false
+                }
+                None => true,
+            },
+        );
+    }
+    (
+        removed.into_iter().cloned().collect(),
+        added.into_iter().cloned().collect(),
+    )
+}
+
+/// Describes a change to `what`, where `None` means unset.
+fn describe(what: &str, old: Option<String>, new: Option<String>) -> String {
+    match (old, new) {
+        (None, Some(new)) => format!("sets {} to {}", what, new),
+        (Some(old), None) => format!("unsets {}, which was {}", what, old),
+        (Some(old), Some(new)) => format!("changes {} from {} to {}", what, old, new),
+        (None, None) => format!("changes {}", what),
+    }
+}
+
+impl ApplicationDiff {
+    fn changes(&self, name: &str, changes: &mut Vec<String>) {
+        if let Some(charm) = &self.charm {
+            changes.push(describe(
+                &format!("the charm of `{}`", name),
+                Some(charm.old.clone()),
+                Some(charm.new.clone()),
+            ));
+        }
+        if let Some(revision) = &self.revision {
+            changes.push(describe(
+                &format!("the revision of `{}`", name),
+                revision.old.map(|r| r.to_string()),
+                revision.new.map(|r| r.to_string()),
+            ));
+        }
+        if let Some(units) = &self.num_units {
+            changes.push(describe(
+                &format!("the units of `{}`", name),
+                Some(units.old.to_string()),
+                Some(units.new.to_string()),
+            ));
+        }
+        if let Some(placements) = &self.placements {
+            changes.push(describe(
+                &format!("the placements of `{}`", name),
+                Some(format!("{:?}", placements.old)),
+                Some(format!("{:?}", placements.new)),
+            ));
+        }
+        for (option, change) in &self.options {
+            changes.push(describe(
+                &format!("`{}.{}`", name, option),
+                change.old.as_ref().map(display_value),
+                change.new.as_ref().map(display_value),
+            ));
+        }
+        for (endpoint, change) in &self.bindings {
+            let what = if endpoint.is_empty() {
+                format!("the default binding of `{}`", name)
+            } else {
+                format!("the binding of `{}:{}`", name, endpoint)
+            };
+            changes.push(describe(&what, change.old.clone(), change.new.clone()));
+        }
+    }
+}
+
+impl BundleDiff {
+    /// Describes each difference in a sentence, e.g. changes
+    /// `` `ceph-osd.osd-devices` from "/dev/sdb" to "/dev/sdc" ``.
+    pub fn changes(&self) -> Vec<String> {
+        let mut changes = vec![];
+        for (name, charm) in &self.added_applications {
+            changes.push(format!("adds `{}` ({})", name, charm));
+        }
+        for (name, charm) in &self.removed_applications {
+            changes.push(format!("removes `{}` ({})", name, charm));
+        }
+        for (name, application) in &self.changed_applications {
+            application.changes(name, &mut changes);
+        }
+        for id in &self.added_machines {
+            changes.push(format!("adds machine {}", id));
+        }
+        for id in &self.removed_machines {
+            changes.push(format!("removes machine {}", id));
+        }
+        for (id, machine) in &self.changed_machines {
+            if let Some(series) = &machine.series {
+                changes.push(describe(
+                    &format!("the series of machine {}", id),
+                    series.old.clone(),
+                    series.new.clone(),
+                ));
+            }
+            if let Some(constraints) = &machine.constraints {
+                changes.push(describe(
+                    &format!("the constraints of machine {}", id),
+                    constraints.old.clone(),
+                    constraints.new.clone(),
+                ));
+            }
+        }
+        for [a, b] in &self.added_relations {
+            changes.push(format!("adds the relation `{}` <-> `{}`", a, b));
+        }
+        for [a, b] in &self.removed_relations {
+            changes.push(format!("removes the relation `{}` <-> `{}`", a, b));
+        }
+        changes
+    }
+}
+
+impl fmt::Display for BundleDiff {
+    /// One line per difference, as described by
+    /// [`changes`](BundleDiff::changes).
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in self.changes() {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
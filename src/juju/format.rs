@@ -0,0 +1,1113 @@
+use failure::Error;
+use serde_yaml::Value;
+use std::cmp::Ordering;
+
+use super::Bundle;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLE: &str = r#"# An OpenStack bundle
+# for testing.
+
+relations:
+# Identity
+- - mysql:shared-db
+  - keystone:shared-db
+- [ceph-osd:mon, ceph-mon:osd]  # storage
+machines:
+  1:
+    series: focal
+  '0': {}
+  10: {}
+applications:
+  keystone:
+    options:
+      worker-multiplier: 0.25
+      # Keep in sync with the certificate.
+      admin-password: 'secret # not a comment'
+      debug: True
+    num_units: 1
+    to: [lxd:0]
+    charm: cs:keystone
+  ceph-osd:
+    to:
+    - 0
+    - "1"
+    num_units: 2
+    charm: cs:ceph-osd
+    options:
+      osd-devices: |
+        /dev/sdb
+          # indented, and not a comment
+        /dev/sdc
+  ceph-mon:
+    charm: cs:ceph-mon
+    num_units: 1
+    to:
+      - lxd:10
+  mysql:
+    charm: cs:percona-cluster
+series: focal
+# The end.
+"#;
+
+    const FORMATTED: &str = r#"# An OpenStack bundle
+# for testing.
+
+series: focal
+applications:
+  ceph-mon:
+    charm: cs:ceph-mon
+    num_units: 1
+    to:
+      - "lxd:10"
+  ceph-osd:
+    charm: cs:ceph-osd
+    num_units: 2
+    to:
+      - "0"
+      - "1"
+    options:
+      osd-devices: |
+        /dev/sdb
+          # indented, and not a comment
+        /dev/sdc
+  keystone:
+    charm: cs:keystone
+    num_units: 1
+    to:
+      - "lxd:0"
+    options:
+      # Keep in sync with the certificate.
+      admin-password: 'secret # not a comment'
+      debug: True
+      worker-multiplier: 0.25
+  mysql:
+    charm: cs:percona-cluster
+machines:
+  "0": {}
+  "1":
+    series: focal
+  "10": {}
+relations:
+  - [ceph-mon:osd, ceph-osd:mon]  # storage
+  # Identity
+  - [keystone:shared-db, mysql:shared-db]
+# The end.
+"#;
+
+    #[test]
+    fn it_formats_a_bundle() {
+        assert_eq!(format_bundle(BUNDLE).unwrap(), FORMATTED);
+    }
+
+    #[test]
+    fn it_is_idempotent() {
+        assert_eq!(format_bundle(FORMATTED).unwrap(), FORMATTED);
+    }
+
+    #[test]
+    fn it_keeps_the_bundle() {
+        let before = Bundle::load(BUNDLE).unwrap();
+        let after = Bundle::load(&format_bundle(BUNDLE).unwrap()).unwrap();
+        assert!(before.diff(&after).is_empty());
+        assert_eq!(
+            after
+                .application("keystone")
+                .unwrap()
+                .option("admin-password"),
+            Some(&"secret # not a comment".into())
+        );
+    }
+
+    #[test]
+    fn it_formats_the_test_bundle() {
+        let bundle = include_str!("../../tests/test_bundle.yaml");
+        let formatted = format_bundle(bundle).unwrap();
+        assert_eq!(format_bundle(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn it_rejects_what_it_cannot_read() {
+        assert!(format_bundle("- a\n- b\n").is_err());
+        assert!(format_bundle("applications:\n  keystone:\n      charm: a\n    x: b\n").is_err());
+    }
+
+    #[test]
+    fn it_refuses_flow_mappings_it_would_sort() {
+        let error = |bundle| format_bundle(bundle).unwrap_err().to_string();
+        assert_eq!(
+            error("applications: {b: {charm: ch:b}, a: {charm: ch:a}}\n"),
+            "Flow mappings are not supported in applications: \"{b: {charm: ch:b}, a: {charm: ch:a}}\""
+        );
+        assert_eq!(
+            error("applications: {}\nmachines: {'1': {}, '0': {}}\n"),
+            r#"Flow mappings are not supported in machines: "{'1': {}, '0': {}}""#
+        );
+        assert_eq!(
+            error("applications:\n  a:\n    charm: ch:a\n    options: {z: 1, a: 2}\n"),
+            "Flow mappings are not supported in a options: \"{z: 1, a: 2}\""
+        );
+        assert_eq!(
+            error("applications:\n  a: {options: {}, charm: ch:a}\n"),
+            "Flow mappings are not supported in a: \"{options: {}, charm: ch:a}\""
+        );
+        assert_eq!(
+            error("{applications: {}}\n"),
+            "Flow mappings are not supported in the bundle"
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_document_start() {
+        let bundle = "---\n# Keystone\n\napplications:\n  a:\n    charm: ch:a\n";
+        assert_eq!(format_bundle(bundle).unwrap(), bundle);
+        assert!(format_bundle("applications: {}\n---\napplications: {}\n").is_err());
+    }
+
+    #[test]
+    fn it_refuses_anchors() {
+        let bundle = r#"applications:
+  keystone:
+    charm: cs:keystone
+    options: &defaults
+      debug: true
+  glance:
+    charm: cs:glance
+    options: *defaults
+"#;
+        assert!(Bundle::load(bundle).is_ok());
+        let error = format_bundle(bundle).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Anchors and aliases are not supported: \"&defaults\""
+        );
+        let error = format_bundle("relations:\n  - [*keystone, glance]\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Anchors and aliases are not supported: \"[*keystone, glance]\""
+        );
+    }
+
+    #[test]
+    fn it_compares_the_whole_bundle() {
+        let load = |yaml: &str| normalize(serde_yaml::from_str(yaml).unwrap());
+        assert_eq!(
+            load("machines: {0: {}}\napplications: {a: {to: 0}}\nrelations: [[b, a]]"),
+            load("machines: {'0': {}}\napplications: {a: {to: ['0']}}\nrelations: [[a, b]]")
+        );
+        assert_ne!(
+            load("applications: {a: {expose: true}}"),
+            load("applications: {a: {expose: false}}")
+        );
+        assert_ne!(load("description: a"), load("description: b"));
+    }
+
+    #[test]
+    fn it_refuses_to_drop_comments_in_flow_collections() {
+        let bundle = r#"applications:
+  keystone:
+    charm: cs:keystone
+    to: [lxd:0,
+         lxd:1]  # spread
+"#;
+        let error = format_bundle(bundle).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Comments inside a flow collection are not supported: \"# spread\""
+        );
+    }
+}
+
+/// The order of the top level sections of a bundle.
+const SECTIONS: &[&str] = &[
+    "description",
+    "series",
+    "base",
+    "variables",
+    "saas",
+    "applications",
+    "machines",
+    "relations",
+];
+
+/// The order of the fields of an application.
+const APPLICATION_FIELDS: &[&str] = &[
+    "charm",
+    "channel",
+    "revision",
+    "series",
+    "base",
+    "num_units",
+    "to",
+    "expose",
+    "constraints",
+    "storage",
+    "devices",
+    "resources",
+    "options",
+    "annotations",
+    "bindings",
+];
+
+/// Rewrites a bundle in canonical form, keeping its comments:
+///
+/// - sections, and the fields of each application, in a fixed order, with
+///   applications, machines, options and bindings sorted by name;
+/// - machine ids and `to:` placements double quoted;
+/// - each relation as a flow sequence with its ends sorted, and the
+///   relations sorted.
+///
+/// Comments on their own lines move with the entry that follows them, and
+/// comments at the end of a line with that line. Blank lines are dropped,
+/// except after the comments that start the file. Scalars are written as
+/// they were, including multi-line strings.
+///
+/// Only the block YAML that bundles are written in is supported, with a
+/// single document and without comments inside flow collections or flow
+/// mappings where entries are sorted. Formatting fails rather than change
+/// what the bundle means or drop a comment.
+pub fn format_bundle(input: &str) -> Result<String, Error> {
+    let (marked, header, document) = Document::parse(input)?;
+    let mut root = match document {
+        Node::Mapping(mapping) => mapping,
+        Node::Flow(text) if text.starts_with('{') => {
+            return Err(format_err!("Flow mappings are not supported in the bundle"))
+        }
+        _ => return Err(format_err!("A bundle must be a mapping")),
+    };
+    canonicalize(&mut root)?;
+    let mut lines = vec![];
+    if marked {
+        lines.push("---".to_string());
+    }
+    if !header.is_empty() {
+        lines.extend(header);
+        lines.push(String::new());
+    }
+    render_mapping(&root, 0, &mut lines);
+    let output = lines.join("\n") + "\n";
+
+    Bundle::load(input)?;
+    let before = normalize(serde_yaml::from_str(input)?);
+    let after = serde_yaml::from_str(&output)
+        .map(normalize)
+        .map_err(|e| format_err!("Formatting produced an invalid bundle: {}", e))?;
+    if before != after {
+        return Err(format_err!("Formatting would change the bundle"));
+    }
+    Ok(output)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    /// A key with no value.
+    Empty,
+    /// A scalar as it was written, with any continuation lines.
+    Scalar(String),
+    /// A flow collection, such as `[a, b]` or `{}`.
+    Flow(String),
+    /// A literal or folded block scalar: its header, e.g. `|`, and its lines
+    /// with their indentation.
+    Block(String, Vec<(usize, String)>),
+    Mapping(Mapping),
+    Sequence(Sequence),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Mapping {
+    entries: Vec<Entry>,
+    /// Comments after the last entry.
+    footer: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    comments: Vec<String>,
+    key: String,
+    value: Node,
+    trailing: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Sequence {
+    items: Vec<Item>,
+    footer: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Item {
+    comments: Vec<String>,
+    value: Node,
+    trailing: Option<String>,
+}
+
+struct Line {
+    indent: usize,
+    text: String,
+}
+
+impl Line {
+    fn is_blank(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+
+    fn is_comment(&self) -> bool {
+        self.text.starts_with('#')
+    }
+
+    fn is_item(&self) -> bool {
+        self.text == "-" || self.text.starts_with("- ")
+    }
+}
+
+struct Document {
+    lines: Vec<Line>,
+    position: usize,
+    /// Comments read but not yet attached, with their indentation.
+    pending: Vec<(usize, String)>,
+}
+
+impl Document {
+    /// Parses a document into whether it starts with `---`, the comments
+    /// that start it, if they are followed by a blank line, and its root
+    /// node.
+    fn parse(input: &str) -> Result<(bool, Vec<String>, Node), Error> {
+        let mut lines: Vec<Line> = input
+            .lines()
+            .map(|line| {
+                let text = line.trim_start();
+                Line {
+                    indent: line.len() - text.len(),
+                    text: text.trim_end().to_string(),
+                }
+            })
+            .collect();
+        if lines.iter().any(|line| line.text.starts_with('\t')) {
+            return Err(format_err!("Tabs can't be used for indentation"));
+        }
+        let markers: Vec<_> = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.indent == 0 && line.text == "---")
+            .map(|(index, _)| index)
+            .collect();
+        let marked = match markers[..] {
+            [] => false,
+            [index]
+                if lines[..index]
+                    .iter()
+                    .all(|l| l.is_blank() || l.is_comment()) =>
+            {
+                lines.remove(index);
+                true
+            }
+            _ => return Err(format_err!("Only a single document is supported")),
+        };
+        let mut header = vec![];
+        let start = lines
+            .iter()
+            .position(|line| !line.is_blank() && !line.is_comment())
+            .unwrap_or(lines.len());
+        if lines[..start].last().map(Line::is_blank).unwrap_or(false) {
+            header = lines[..start]
+                .iter()
+                .filter(|line| line.is_comment())
+                .map(|line| line.text.clone())
+                .collect();
+            lines.drain(..start);
+        }
+        let mut document = Document {
+            lines,
+            position: 0,
+            pending: vec![],
+        };
+        document.skip();
+        let root = match document.current() {
+            Some(line) if line.indent == 0 => document.node(0)?,
+            Some(_) => return Err(format_err!("The document must start unindented")),
+            None => Node::Mapping(Mapping::default()),
+        };
+        document.skip();
+        if let Some(line) = document.current() {
+            return Err(format_err!("Unexpected {:?}", line.text));
+        }
+        Ok((marked, header, root))
+    }
+
+    fn current(&self) -> Option<&Line> {
+        self.lines.get(self.position)
+    }
+
+    /// Moves past blank lines, keeping comments to attach to what follows.
+    fn skip(&mut self) {
+        while let Some(line) = self.lines.get(self.position) {
+            if line.is_comment() {
+                self.pending.push((line.indent, line.text.clone()));
+            } else if !line.is_blank() {
+                break;
+            }
+            self.position += 1;
+        }
+    }
+
+    fn take_comments(&mut self) -> Vec<String> {
+        self.pending.drain(..).map(|(_, text)| text).collect()
+    }
+
+    /// Takes the pending comments indented at least to `indent`, which end
+    /// the block at that indentation.
+    fn take_footer(&mut self, indent: usize) -> Vec<String> {
+        let end = self
+            .pending
+            .iter()
+            .position(|(i, _)| *i < indent)
+            .unwrap_or(self.pending.len());
+        self.pending.drain(..end).map(|(_, text)| text).collect()
+    }
+
+    /// Parses the node starting on the current line, at `indent`.
+    fn node(&mut self, indent: usize) -> Result<Node, Error> {
+        let line = match self.current() {
+            Some(line) => line,
+            None => return Ok(Node::Empty),
+        };
+        if line.is_item() {
+            return Ok(Node::Sequence(self.sequence(indent)?));
+        }
+        let (content, trailing) = split_comment(&line.text);
+        if key_separator(&content).is_some() {
+            return Ok(Node::Mapping(self.mapping(indent)?));
+        }
+        if trailing.is_some() {
+            return Err(format_err!("Unexpected comment after {:?}", content));
+        }
+        self.position += 1;
+        self.value(&content, indent.saturating_sub(1))
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<Mapping, Error> {
+        let mut mapping = Mapping::default();
+        loop {
+            self.skip();
+            let line = match self.current() {
+                Some(line) if line.indent == indent && !line.is_item() => line,
+                Some(line) if line.indent > indent => {
+                    return Err(format_err!("Unexpected indentation of {:?}", line.text))
+                }
+                _ => break,
+            };
+            let (content, trailing) = split_comment(&line.text);
+            let separator = key_separator(&content)
+                .ok_or_else(|| format_err!("Expected a key in {:?}", content))?;
+            let key = content[..separator].trim_end().to_string();
+            if is_anchor(&key) {
+                return Err(anchors_unsupported(&key));
+            }
+            let rest = content[separator + 1..].trim().to_string();
+            let comments = self.take_comments();
+            self.position += 1;
+            let value = self.value(&rest, indent)?;
+            mapping.entries.push(Entry {
+                comments,
+                key,
+                value,
+                trailing,
+            });
+        }
+        mapping.footer = self.take_footer(indent);
+        Ok(mapping)
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<Sequence, Error> {
+        let mut sequence = Sequence::default();
+        loop {
+            self.skip();
+            let line = match self.current() {
+                Some(line) if line.indent == indent && line.is_item() => line.text.clone(),
+                Some(line) if line.indent > indent => {
+                    return Err(format_err!("Unexpected indentation of {:?}", line.text))
+                }
+                _ => break,
+            };
+            let comments = self.take_comments();
+            let rest = line[1..].trim_start().to_string();
+            let item_indent = indent + line.len() - rest.len();
+            let (content, trailing) = split_comment(&rest);
+            let item = if rest.is_empty() {
+                self.position += 1;
+                Item {
+                    comments,
+                    value: self.value("", indent)?,
+                    trailing: None,
+                }
+            } else if rest.starts_with("- ") || rest == "-" || key_separator(&content).is_some() {
+                // Reads the rest of the line as the first line of a nested
+                // block, indented to where it starts.
+                self.lines[self.position] = Line {
+                    indent: item_indent,
+                    text: rest,
+                };
+                Item {
+                    comments,
+                    value: self.node(item_indent)?,
+                    trailing: None,
+                }
+            } else {
+                self.position += 1;
+                Item {
+                    comments,
+                    value: self.value(&content, indent)?,
+                    trailing,
+                }
+            };
+            sequence.items.push(item);
+        }
+        sequence.footer = self.take_footer(indent);
+        Ok(sequence)
+    }
+
+    /// Parses a value that starts with `rest`, after a key or a dash at
+    /// `indent`, and any lines that belong to it.
+    fn value(&mut self, rest: &str, indent: usize) -> Result<Node, Error> {
+        if is_anchor(rest) {
+            return Err(anchors_unsupported(rest));
+        }
+        if rest.is_empty() {
+            self.skip();
+            return match self.current() {
+                Some(line) if line.indent > indent => {
+                    let indent = line.indent;
+                    self.node(indent)
+                }
+                Some(line) if line.indent == indent && line.is_item() => {
+                    Ok(Node::Sequence(self.sequence(indent)?))
+                }
+                _ => Ok(Node::Empty),
+            };
+        }
+        if rest.starts_with('|') || rest.starts_with('>') {
+            let mut lines = vec![];
+            while let Some(line) = self.current() {
+                if !line.is_blank() && line.indent <= indent {
+                    break;
+                }
+                lines.push((line.indent, line.text.clone()));
+                self.position += 1;
+            }
+            while lines.last().map(|(_, text)| text.is_empty()) == Some(true) {
+                lines.pop();
+            }
+            return Ok(Node::Block(rest.to_string(), lines));
+        }
+        let mut text = rest.to_string();
+        if rest.starts_with('[') || rest.starts_with('{') {
+            while !is_balanced(&text) {
+                let line = self
+                    .current()
+                    .ok_or_else(|| format_err!("{} is never closed", rest))?;
+                let (content, comment) = split_comment(&line.text);
+                if let Some(comment) = comment {
+                    return Err(format_err!(
+                        "Comments inside a flow collection are not supported: {:?}",
+                        comment
+                    ));
+                }
+                text = format!("{} {}", text, content);
+                self.position += 1;
+            }
+            if has_anchor(&text) {
+                return Err(anchors_unsupported(&text));
+            }
+            return Ok(Node::Flow(text));
+        }
+        while let Some(line) = self.current() {
+            if line.is_blank() || line.is_comment() || line.indent <= indent {
+                break;
+            }
+            text = format!("{}\n{}", text, line.text);
+            self.position += 1;
+        }
+        Ok(Node::Scalar(text))
+    }
+}
+
+/// Splits the comment off the end of a line, outside of quotes.
+fn split_comment(text: &str) -> (String, Option<String>) {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (index, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '#' && previous.is_whitespace() => {
+                return (
+                    text[..index].trim_end().to_string(),
+                    Some(text[index..].to_string()),
+                );
+            }
+            None => {}
+        }
+        previous = c;
+    }
+    (text.to_string(), None)
+}
+
+/// The index of the `:` that ends a mapping key, outside of quotes and
+/// brackets.
+fn key_separator(text: &str) -> Option<usize> {
+    if text.starts_with('[') || text.starts_with('{') || text.starts_with('|') {
+        return None;
+    }
+    let mut quote = None;
+    let mut depth = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth -= 1,
+                ':' if depth == 0 => match chars.peek() {
+                    None | Some((_, ' ')) => return Some(index),
+                    _ => {}
+                },
+                _ => {}
+            },
+        }
+    }
+    None
+}
+
+fn is_balanced(text: &str) -> bool {
+    let mut quote = None;
+    let mut depth = 0;
+    for c in text.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+    depth <= 0
+}
+
+/// Whether `text` starts with an anchor, `&name`, or an alias, `*name`.
+fn is_anchor(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some('&') | Some('*'))
+        && chars.next().is_some_and(|c| !c.is_whitespace())
+}
+
+/// Whether an item or a value in a flow collection is an anchor or an alias.
+fn has_anchor(text: &str) -> bool {
+    let mut quote = None;
+    for (index, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if "[{,:".contains(c) && is_anchor(text[index + 1..].trim_start()) => return true,
+            None => {}
+        }
+    }
+    false
+}
+
+/// Anchors can't be kept, as sorting could move an alias before its anchor.
+fn anchors_unsupported(text: &str) -> Error {
+    format_err!("Anchors and aliases are not supported: {:?}", text)
+}
+
+/// A key or scalar without its quotes.
+fn unquote(text: &str) -> String {
+    let text = text.trim();
+    if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        text[1..text.len() - 1]
+            .replace("\\\"", "\"")
+            .replace("\\\\", "\\")
+    } else if text.len() >= 2 && text.starts_with('\'') && text.ends_with('\'') {
+        text[1..text.len() - 1].replace("''", "'")
+    } else {
+        text.to_string()
+    }
+}
+
+fn double_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A relation endpoint, quoted only if it has to be.
+fn endpoint(text: &str) -> String {
+    let plain = !text.is_empty()
+        && !text.contains(": ")
+        && !text.contains(" #")
+        && !text.ends_with(':')
+        && !text.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        && !text.contains([',', '[', ']', '{', '}']);
+    if plain {
+        text.to_string()
+    } else {
+        double_quote(text)
+    }
+}
+
+/// Splits a flow sequence of scalars into its unquoted items.
+fn flow_items(text: &str) -> Option<Vec<String>> {
+    let inner = text.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let mut items = vec![];
+    let mut quote = None;
+    let mut start = 0;
+    for (index, c) in inner.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == '[' || c == '{' => return None,
+            None if c == ',' => {
+                items.push(unquote(&inner[start..index]));
+                start = index + 1;
+            }
+            None => {}
+        }
+    }
+    if !inner[start..].trim().is_empty() {
+        items.push(unquote(&inner[start..]));
+    }
+    Some(items)
+}
+
+/// The items of a sequence of scalars, written either way, with the
+/// comments on the items of a block sequence.
+fn scalar_items(node: &Node) -> Option<(Vec<String>, Vec<String>)> {
+    match node {
+        Node::Flow(text) => Some((flow_items(text)?, vec![])),
+        Node::Sequence(sequence) => {
+            let mut comments = vec![];
+            let mut items = vec![];
+            for item in &sequence.items {
+                match &item.value {
+                    Node::Scalar(text) if !text.contains('\n') => items.push(unquote(text)),
+                    _ => return None,
+                }
+                comments.extend(item.comments.iter().cloned());
+                comments.extend(item.trailing.iter().cloned());
+            }
+            comments.extend(sequence.footer.iter().cloned());
+            Some((items, comments))
+        }
+        _ => None,
+    }
+}
+
+/// Sorts entries by their place in `order`, keeping the order of the rest,
+/// which go last.
+fn sort_by_order(mapping: &mut Mapping, order: &[&str]) {
+    let rank = |entry: &Entry| {
+        let key = unquote(&entry.key);
+        order
+            .iter()
+            .position(|name| *name == key)
+            .unwrap_or(order.len())
+    };
+    mapping.entries.sort_by_key(rank);
+}
+
+fn sort_by_name(mapping: &mut Mapping) {
+    mapping.entries.sort_by_key(|entry| unquote(&entry.key));
+}
+
+fn entry_mut<'a>(mapping: &'a mut Mapping, key: &str) -> Option<&'a mut Entry> {
+    mapping
+        .entries
+        .iter_mut()
+        .find(|entry| unquote(&entry.key) == key)
+}
+
+/// The mapping in `node`, to be sorted, or `None` if `node` isn't a
+/// mapping. A flow mapping with entries can't be sorted, so it is an error.
+fn sortable<'a>(node: &'a mut Node, what: &str) -> Result<Option<&'a mut Mapping>, Error> {
+    match node {
+        Node::Mapping(mapping) => Ok(Some(mapping)),
+        Node::Flow(text) if text.starts_with('{') && text[1..].trim() != "}" => Err(format_err!(
+            "Flow mappings are not supported in {}: {:?}",
+            what,
+            text
+        )),
+        _ => Ok(None),
+    }
+}
+
+fn canonicalize(root: &mut Mapping) -> Result<(), Error> {
+    sort_by_order(root, SECTIONS);
+    if let Some(entry) = entry_mut(root, "applications") {
+        if let Some(applications) = sortable(&mut entry.value, "applications")? {
+            sort_by_name(applications);
+            for entry in &mut applications.entries {
+                let what = unquote(&entry.key);
+                if let Some(application) = sortable(&mut entry.value, &what)? {
+                    canonicalize_application(application, &what)?;
+                }
+            }
+        }
+    }
+    if let Some(machines) = match entry_mut(root, "machines") {
+        Some(entry) => sortable(&mut entry.value, "machines")?,
+        None => None,
+    } {
+        for entry in &mut machines.entries {
+            entry.key = double_quote(&unquote(&entry.key));
+        }
+        machines
+            .entries
+            .sort_by(|a, b| machine_order(&a.key, &b.key));
+    }
+    if let Some(entry) = entry_mut(root, "relations") {
+        if let Node::Sequence(relations) = &mut entry.value {
+            canonicalize_relations(relations);
+        }
+    }
+    Ok(())
+}
+
+/// Orders machines by number, then any other ids by name.
+fn machine_order(a: &str, b: &str) -> Ordering {
+    let (a, b) = (unquote(a), unquote(b));
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(x), Ok(y)) => x.cmp(&y),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(&b),
+    }
+}
+
+fn canonicalize_application(application: &mut Mapping, name: &str) -> Result<(), Error> {
+    sort_by_order(application, APPLICATION_FIELDS);
+    for field in &["options", "bindings"] {
+        if let Some(entry) = entry_mut(application, field) {
+            let what = format!("{} {}", name, field);
+            if let Some(mapping) = sortable(&mut entry.value, &what)? {
+                sort_by_name(mapping);
+            }
+        }
+    }
+    if let Some(entry) = entry_mut(application, "to") {
+        let (placements, comments) = match &entry.value {
+            Node::Scalar(text) if !text.contains('\n') => (vec![unquote(text)], vec![]),
+            value => match scalar_items(value) {
+                Some(items) => items,
+                None => return Ok(()),
+            },
+        };
+        let mut items: Vec<Item> = placements
+            .iter()
+            .map(|placement| Item {
+                comments: vec![],
+                value: Node::Scalar(double_quote(placement)),
+                trailing: None,
+            })
+            .collect();
+        if let Some(first) = items.first_mut() {
+            first.comments = comments;
+        }
+        entry.value = Node::Sequence(Sequence {
+            items,
+            footer: vec![],
+        });
+    }
+    Ok(())
+}
+
+fn canonicalize_relations(relations: &mut Sequence) {
+    for item in &mut relations.items {
+        if let Some((mut ends, comments)) = scalar_items(&item.value) {
+            ends.sort();
+            let ends: Vec<_> = ends.iter().map(|end| endpoint(end)).collect();
+            item.value = Node::Flow(format!("[{}]", ends.join(", ")));
+            item.comments.extend(comments);
+        }
+    }
+    relations.items.sort_by(|a, b| match (&a.value, &b.value) {
+        (Node::Flow(a), Node::Flow(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    });
+}
+
+/// The bundle as data, without what formatting may change: the type of
+/// machine ids and placements, a placement written as a single string, and
+/// the order of relations and their ends.
+fn normalize(mut bundle: Value) -> Value {
+    fn string(value: &Value) -> Value {
+        match value {
+            Value::Number(number) => Value::String(number.to_string()),
+            value => value.clone(),
+        }
+    }
+    fn sort(values: &mut [Value]) {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    }
+    if let Some(machines) = bundle.get_mut("machines").and_then(Value::as_mapping_mut) {
+        *machines = machines
+            .iter()
+            .map(|(id, machine)| (string(id), machine.clone()))
+            .collect();
+    }
+    if let Some(applications) = bundle
+        .get_mut("applications")
+        .and_then(Value::as_mapping_mut)
+    {
+        for (_, application) in applications.iter_mut() {
+            if let Some(to) = application.get_mut("to") {
+                *to = match &*to {
+                    Value::Sequence(placements) => placements.iter().map(string).collect(),
+                    placement => Value::Sequence(vec![string(placement)]),
+                };
+            }
+        }
+    }
+    if let Some(relations) = bundle.get_mut("relations").and_then(Value::as_sequence_mut) {
+        for relation in relations.iter_mut() {
+            if let Some(ends) = relation.as_sequence_mut() {
+                sort(ends);
+            }
+        }
+        sort(relations);
+    }
+    bundle
+}
+
+fn pad(indent: usize) -> String {
+    " ".repeat(indent)
+}
+
+fn with_trailing(line: String, trailing: &Option<String>) -> String {
+    match trailing {
+        Some(comment) => format!("{}  {}", line, comment),
+        None => line,
+    }
+}
+
+/// Moves the comments before the first entry of a nested block onto the
+/// item that holds it, as they can't follow the dash.
+fn lift_comments(node: &mut Node) -> Vec<String> {
+    match node {
+        Node::Mapping(mapping) => match mapping.entries.first_mut() {
+            Some(entry) => std::mem::take(&mut entry.comments),
+            None => vec![],
+        },
+        Node::Sequence(sequence) => match sequence.items.first_mut() {
+            Some(item) => {
+                let mut comments = std::mem::take(&mut item.comments);
+                comments.extend(lift_comments(&mut item.value));
+                comments
+            }
+            None => vec![],
+        },
+        _ => vec![],
+    }
+}
+
+fn render_block_scalar(lines: &[(usize, String)], indent: usize, out: &mut Vec<String>) {
+    let base = lines
+        .iter()
+        .filter(|(_, text)| !text.is_empty())
+        .map(|(i, _)| *i)
+        .min()
+        .unwrap_or(0);
+    for (i, text) in lines {
+        if text.is_empty() {
+            out.push(String::new());
+        } else {
+            out.push(format!("{}{}", pad(indent + i - base), text));
+        }
+    }
+}
+
+fn render_scalar(text: &str, indent: usize) -> String {
+    text.replace('\n', &format!("\n{}", pad(indent)))
+}
+
+fn render_mapping(mapping: &Mapping, indent: usize, out: &mut Vec<String>) {
+    for entry in &mapping.entries {
+        for comment in &entry.comments {
+            out.push(format!("{}{}", pad(indent), comment));
+        }
+        let key = format!("{}{}:", pad(indent), entry.key);
+        match &entry.value {
+            Node::Empty => out.push(with_trailing(key, &entry.trailing)),
+            Node::Scalar(text) | Node::Flow(text) => out.push(with_trailing(
+                format!("{} {}", key, render_scalar(text, indent + 2)),
+                &entry.trailing,
+            )),
+            Node::Block(header, lines) => {
+                out.push(with_trailing(
+                    format!("{} {}", key, header),
+                    &entry.trailing,
+                ));
+                render_block_scalar(lines, indent + 2, out);
+            }
+            Node::Mapping(child) => {
+                out.push(with_trailing(key, &entry.trailing));
+                render_mapping(child, indent + 2, out);
+            }
+            Node::Sequence(child) => {
+                out.push(with_trailing(key, &entry.trailing));
+                render_sequence(child, indent + 2, out);
+            }
+        }
+    }
+    for comment in &mapping.footer {
+        out.push(format!("{}{}", pad(indent), comment));
+    }
+}
+
+fn render_sequence(sequence: &Sequence, indent: usize, out: &mut Vec<String>) {
+    for item in &sequence.items {
+        let mut value = item.value.clone();
+        let mut comments = item.comments.clone();
+        comments.extend(lift_comments(&mut value));
+        for comment in &comments {
+            out.push(format!("{}{}", pad(indent), comment));
+        }
+        let dash = format!("{}-", pad(indent));
+        match &value {
+            Node::Empty => out.push(with_trailing(dash, &item.trailing)),
+            Node::Scalar(text) | Node::Flow(text) => out.push(with_trailing(
+                format!("{} {}", dash, render_scalar(text, indent + 2)),
+                &item.trailing,
+            )),
+            Node::Block(header, lines) => {
+                out.push(with_trailing(
+                    format!("{} {}", dash, header),
+                    &item.trailing,
+                ));
+                render_block_scalar(lines, indent + 2, out);
+            }
+            Node::Mapping(_) | Node::Sequence(_) => {
+                let mut lines = vec![];
+                match &value {
+                    Node::Mapping(child) => render_mapping(child, indent + 2, &mut lines),
+                    Node::Sequence(child) => render_sequence(child, indent + 2, &mut lines),
+                    _ => unreachable!("Matched above"),
+                }
+                if let Some(first) = lines.first_mut() {
+                    *first = format!("{} {}", dash, &first[indent + 2..]);
+                }
+                out.extend(lines);
+            }
+        }
+    }
+    for comment in &sequence.footer {
+        out.push(format!("{}{}", pad(indent), comment));
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use failure::Error;
+use log::debug;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/includes")
+    }
+
+    #[test]
+    fn it_resolves_an_included_file() {
+        let resolver = IncludeResolver::new(fixtures());
+        let value = resolver
+            .resolve(&"include-file://certs/ca.pem".into())
+            .unwrap();
+        assert_eq!(
+            value,
+            Some("-----BEGIN CERTIFICATE-----\ntest\n-----END CERTIFICATE-----\n".into())
+        );
+    }
+
+    #[test]
+    fn it_resolves_a_base64_include() {
+        let resolver = IncludeResolver::new(fixtures());
+        let value = resolver
+            .resolve(&"include-base64://certs/ca.pem".into())
+            .unwrap();
+        assert_eq!(
+            value,
+            Some(
+                "LS0tLS1CRUdJTiBDRVJUSUZJQ0FURS0tLS0tCnRlc3QKLS0tLS1FTkQgQ0VSVElGSUNBVEUtLS0tLQo="
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn it_ignores_plain_values() {
+        let resolver = IncludeResolver::new(fixtures());
+        assert_eq!(resolver.resolve(&"certs/ca.pem".into()).unwrap(), None);
+        assert_eq!(resolver.resolve(&true.into()).unwrap(), None);
+    }
+
+    #[test]
+    fn it_rejects_paths_outside_the_bundle() {
+        let resolver = IncludeResolver::new(fixtures().join("certs"));
+        assert!(resolver
+            .resolve(&"include-file://../bundle.yaml".into())
+            .is_err());
+    }
+
+    #[test]
+    fn it_rejects_oversized_includes() {
+        let resolver = IncludeResolver::new(fixtures()).with_max_size(8);
+        assert_eq!(
+            resolver
+                .resolve(&"include-file://certs/ca.pem".into())
+                .unwrap_err()
+                .to_string(),
+            "certs/ca.pem is larger than the limit of 8 bytes"
+        );
+        let resolver = IncludeResolver::new(fixtures()).with_max_size(59);
+        assert!(resolver
+            .resolve(&"include-file://certs/ca.pem".into())
+            .is_ok());
+    }
+}
+
+const INCLUDE_FILE: &str = "include-file://";
+const INCLUDE_BASE64: &str = "include-base64://";
+
+/// The largest file that will be read for an include directive, in bytes.
+pub const DEFAULT_MAX_INCLUDE_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Fail)]
+pub enum IncludeError {
+    #[fail(display = "{} is outside of the bundle directory", path)]
+    OutsideBundle { path: String },
+    #[fail(display = "{} is larger than the limit of {} bytes", path, limit)]
+    TooLarge { path: String, limit: u64 },
+}
+
+/// Resolves `include-file://` and `include-base64://` option values relative
+/// to the directory that contains the bundle.
+#[derive(Debug)]
+pub struct IncludeResolver {
+    base: PathBuf,
+    max_size: u64,
+}
+
+impl IncludeResolver {
+    pub fn new<P: Into<PathBuf>>(base: P) -> IncludeResolver {
+        IncludeResolver {
+            base: base.into(),
+            max_size: DEFAULT_MAX_INCLUDE_SIZE,
+        }
+    }
+
+    pub fn with_max_size(mut self, max_size: u64) -> IncludeResolver {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Returns the included content if `value` is an include directive, or
+    /// `None` if it is an ordinary option value.
+    pub fn resolve(&self, value: &serde_yaml::Value) -> Result<Option<serde_yaml::Value>, Error> {
+        let value = match value.as_str() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        if let Some(path) = value.strip_prefix(INCLUDE_FILE) {
+            let content = self.read(path)?;
+            Ok(Some(String::from_utf8(content)?.into()))
+        } else if let Some(path) = value.strip_prefix(INCLUDE_BASE64) {
+            let content = self.read(path)?;
+            Ok(Some(base64::encode(&content).into()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let base = self.base.canonicalize()?;
+        let full_path = sandboxed(&base, Path::new(path))?;
+        debug!("Including {}", full_path.display());
+        // Reads one byte past the limit, rather than checking the size first,
+        // so that a file growing in between can't get past it.
+        let mut content = vec![];
+        File::open(full_path)?
+            .take(self.max_size + 1)
+            .read_to_end(&mut content)?;
+        if content.len() as u64 > self.max_size {
+            return Err(IncludeError::TooLarge {
+                path: path.to_string(),
+                limit: self.max_size,
+            }
+            .into());
+        }
+        Ok(content)
+    }
+}
+
+/// Whether resolving includes failed because a file couldn't be read, rather
+/// than because of what the bundle asks to include.
+pub fn is_unreadable(error: &Error) -> bool {
+    error
+        .iter_chain()
+        .any(|cause| cause.downcast_ref::<io::Error>().is_some())
+}
+
+fn sandboxed(base: &Path, path: &Path) -> Result<PathBuf, Error> {
+    let full_path = base.join(path).canonicalize()?;
+    if !full_path.starts_with(base) {
+        return Err(IncludeError::OutsideBundle {
+            path: path.display().to_string(),
+        }
+        .into());
+    }
+    Ok(full_path)
+}
@@ -6,16 +6,16 @@ use failure::Error;
 mod tests {
     use super::*;
 
-    const MACHINE: &'static str = "constraints: virt-type=kvm";
+    const MACHINE: &str = "constraints: virt-type=kvm";
 
     #[test]
     fn it_parses_a_machine() {
-        let machine = Machine::parse(&MACHINE).unwrap();
+        let machine = Machine::parse(MACHINE).unwrap();
         assert_eq!(machine.constraints.unwrap(), "virt-type=kvm");
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Machine {
     series: Option<String>,
     constraints: Option<String>,
@@ -23,6 +23,14 @@ pub struct Machine {
 
 impl Machine {
     pub fn parse(input: &str) -> Result<Machine, Error> {
-        Ok(serde_yaml::from_str(&input)?)
+        Ok(serde_yaml::from_str(input)?)
+    }
+
+    pub fn series(&self) -> Option<&str> {
+        self.series.as_deref()
+    }
+
+    pub fn constraints(&self) -> Option<&str> {
+        self.constraints.as_deref()
     }
 }
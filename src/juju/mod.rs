@@ -1,9 +1,27 @@
 mod application;
 mod bundle;
+mod cli;
+mod diff;
+mod format;
+mod include;
 mod machine;
 mod model;
+mod overlay;
+mod relation;
+mod status;
+mod variables;
 
 pub use application::Application;
 pub use bundle::Bundle;
+pub use cli::Juju;
+pub use diff::{ApplicationDiff, BundleDiff, Change, MachineDiff};
+pub use format::format_bundle;
+pub use include::{is_unreadable, IncludeError, IncludeResolver, DEFAULT_MAX_INCLUDE_SIZE};
 pub use machine::Machine;
 pub use model::Model;
+pub use overlay::Overlay;
+pub use relation::{pair_matches, RelationEndpoint};
+pub use status::{
+    ApplicationStatus, Base, MachineStatus, ModelStatus, Status, StatusInfo, UnitStatus,
+};
+pub use variables::VariableIssue;
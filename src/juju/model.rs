@@ -1,29 +1,203 @@
 use failure::Error;
-use log::debug;
+use serde::Deserialize;
+use std::env;
 use std::fs;
+use std::io;
 use std::path::PathBuf;
-use std::process::Command;
 
 use super::bundle::Bundle;
+use super::{is_unreadable, IncludeResolver, Juju, Status};
+use crate::JujuLintError;
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_resolves_includes_relative_to_the_bundle() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/includes/bundle.yaml");
+        let bundle = Model::load_bundle(path).unwrap();
+        let keystone = bundle.application("keystone").unwrap();
+        assert_eq!(
+            keystone.raw_option("ssl_cert").unwrap(),
+            "include-file://certs/ca.pem"
+        );
+        assert_eq!(
+            keystone.option("ssl_cert").unwrap(),
+            "-----BEGIN CERTIFICATE-----\ntest\n-----END CERTIFICATE-----\n"
+        );
+    }
+
+    #[test]
+    fn it_exports_a_model() {
+        let juju =
+            Juju::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/juju/fake-juju"));
+        let bundle = Model::export_bundle(&juju, "ctrl:openstack").unwrap();
+        assert!(bundle.application("keystone").is_some());
+    }
+
+    #[test]
+    fn it_loads_a_status() {
+        let juju =
+            Juju::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/juju/fake-juju"));
+        let status = Model::status(&juju, "ctrl:openstack").unwrap();
+        assert_eq!(status.applications["keystone"].units.len(), 2);
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/status/openstack.yaml");
+        assert_eq!(Model::load_status(path).unwrap(), status);
+    }
+
+    #[test]
+    fn it_lists_models() {
+        let juju =
+            Juju::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/juju/fake-juju"));
+        assert_eq!(
+            Model::list(&juju, None).unwrap(),
+            vec!["ctrl:admin/openstack", "ctrl:admin/missing"]
+        );
+        assert_eq!(
+            Model::list(&juju, Some("other")).unwrap(),
+            vec!["other:admin/openstack", "other:admin/missing"]
+        );
+    }
+
+    #[test]
+    fn it_tells_io_errors_from_parse_errors() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests");
+        match Model::load_bundle(path.join("missing.yaml")) {
+            Err(JujuLintError::BundleIo { .. }) => {}
+            other => panic!("Expected an I/O error, got {:?}", other),
+        }
+        match Model::load_bundle(path.join("rules/broken/broken.yaml")) {
+            Err(JujuLintError::BundleParse { .. }) => {}
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+        match Model::load_bundle(path.join("includes/unreadable.yaml")) {
+            Err(JujuLintError::BundleIo { reason, .. }) => assert!(
+                reason.starts_with("Failed to resolve includes for keystone: "),
+                "{}",
+                reason
+            ),
+            other => panic!("Expected an I/O error, got {:?}", other),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct Model;
 
+/// The parts of `juju models --format json` we use.
+#[derive(Deserialize)]
+struct ModelList {
+    models: Vec<ModelSummary>,
+}
+
+#[derive(Deserialize)]
+struct ModelSummary {
+    name: String,
+    #[serde(rename = "controller-name")]
+    controller: Option<String>,
+}
+
 impl Model {
-    pub fn export_bundle(model: &str) -> Result<Bundle, Error> {
-        let mut cmd = Command::new("juju");
-        cmd.arg("export-bundle").arg("--model").arg(model);
-        debug!("About to run {:?}", cmd);
-        let output = cmd.output()?;
-        debug!("Got output from juju: {:?}", output);
-        Bundle::load(&String::from_utf8_lossy(&output.stdout))
+    /// Lists the models on `controller`, or on the current controller, as
+    /// `controller:owner/model` names that can be passed to
+    /// [`export_bundle`](Model::export_bundle).
+    pub fn list(juju: &Juju, controller: Option<&str>) -> Result<Vec<String>, JujuLintError> {
+        let mut args = vec!["models", "--format", "json"];
+        if let Some(controller) = controller {
+            args.extend(&["--controller", controller]);
+        }
+        let output = juju.run(&args)?;
+        let list: ModelList =
+            serde_json::from_str(&output).map_err(|e| JujuLintError::JujuCli {
+                command: args.join(" "),
+                reason: format!("Unexpected output: {}", e),
+            })?;
+        Ok(list
+            .models
+            .into_iter()
+            .map(|model| match controller.or(model.controller.as_deref()) {
+                Some(controller) => format!("{}:{}", controller, model.name),
+                None => model.name,
+            })
+            .collect())
+    }
+
+    /// Exports the bundle of a deployed model, named `model` or
+    /// `controller:model`.
+    pub fn export_bundle(juju: &Juju, model: &str) -> Result<Bundle, JujuLintError> {
+        let output = juju.run(&["export-bundle", "--model", model])?;
+        Bundle::load(&output).map_err(|e| JujuLintError::BundleParse {
+            path: format!("the bundle of {}", model),
+            reason: e.to_string(),
+        })
     }
 
-    pub fn load_bundle(path: PathBuf) -> Result<Bundle, Error> {
-        let path = if path == PathBuf::from("-") {
+    /// Runs `juju status` for a deployed model.
+    pub fn status(juju: &Juju, model: &str) -> Result<Status, JujuLintError> {
+        let output = juju.run(&["status", "--format", "yaml", "--model", model])?;
+        Status::load(&output).map_err(|e| JujuLintError::BundleParse {
+            path: format!("the status of {}", model),
+            reason: e.to_string(),
+        })
+    }
+
+    /// Loads saved `juju status --format yaml` or `--format json` output
+    /// from `path`, or from stdin when `path` is `-`.
+    pub fn load_status(path: PathBuf) -> Result<Status, JujuLintError> {
+        let display = path.display().to_string();
+        let path = if path.as_os_str() == "-" {
             PathBuf::from("/dev/stdin")
         } else {
             path
         };
-        Bundle::load(&fs::read_to_string(path)?)
+        let input = fs::read_to_string(path).map_err(|e| JujuLintError::BundleIo {
+            path: display.clone(),
+            reason: e.to_string(),
+        })?;
+        Status::load(&input).map_err(|e| JujuLintError::BundleParse {
+            path: display,
+            reason: e.to_string(),
+        })
+    }
+
+    /// Loads a bundle from `path`, or from stdin when `path` is `-`.
+    ///
+    /// Include directives in option values are resolved relative to the
+    /// directory containing the bundle, or the working directory for stdin.
+    pub fn load_bundle(path: PathBuf) -> Result<Bundle, JujuLintError> {
+        let display = path.display().to_string();
+        let io = |e: io::Error| JujuLintError::BundleIo {
+            path: display.clone(),
+            reason: e.to_string(),
+        };
+        let io_failure = |e: Error| JujuLintError::BundleIo {
+            path: display.clone(),
+            reason: e.to_string(),
+        };
+        let parse = |e: Error| JujuLintError::BundleParse {
+            path: display.clone(),
+            reason: e.to_string(),
+        };
+        let (path, base) = if path.as_os_str() == "-" {
+            (PathBuf::from("/dev/stdin"), env::current_dir().map_err(io)?)
+        } else {
+            let base = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => env::current_dir().map_err(io)?,
+            };
+            (path, base)
+        };
+        let mut bundle = Bundle::load(&fs::read_to_string(path).map_err(io)?).map_err(parse)?;
+        bundle
+            .resolve_includes(&IncludeResolver::new(base))
+            .map_err(|e| {
+                if is_unreadable(&e) {
+                    io_failure(e)
+                } else {
+                    parse(e)
+                }
+            })?;
+        Ok(bundle)
     }
 }
@@ -0,0 +1,300 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use failure::Error;
+
+use super::Machine;
+use crate::JujuLintError;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::juju::Bundle;
+
+    const BUNDLE: &str = r#"
+applications:
+  keystone:
+    charm: cs:keystone
+    num_units: 1
+    options:
+      debug: true
+      worker-multiplier: 0.25
+  mysql:
+    charm: cs:percona-cluster
+relations:
+- - keystone:shared-db
+  - mysql:shared-db
+"#;
+
+    #[test]
+    fn it_merges_an_overlay() {
+        let mut bundle = Bundle::load(BUNDLE).unwrap();
+        let overlay = Overlay::parse(
+            r#"
+applications:
+  keystone:
+    num_units: 3
+    options:
+      debug: null
+      vip: 10.0.0.10
+  keystone-hacluster:
+    charm: cs:hacluster
+relations:
+- - keystone:ha
+  - keystone-hacluster:ha
+"#,
+        )
+        .unwrap();
+        bundle.apply_overlay(&overlay).unwrap();
+        let keystone = bundle.application("keystone").unwrap();
+        assert_eq!(keystone.num_units(), 3);
+        assert_eq!(keystone.option("debug"), None);
+        assert_eq!(keystone.option("vip").unwrap(), "10.0.0.10");
+        assert_eq!(keystone.option("worker-multiplier").unwrap(), 0.25);
+        assert!(bundle.application("keystone-hacluster").is_some());
+        assert_eq!(bundle.relations.len(), 2);
+    }
+
+    #[test]
+    fn it_removes_an_application_and_its_relations() {
+        let mut bundle = Bundle::load(BUNDLE).unwrap();
+        let overlay = Overlay::parse("applications: {mysql: null}").unwrap();
+        bundle.apply_overlay(&overlay).unwrap();
+        assert!(bundle.application("mysql").is_none());
+        assert!(bundle.relations.is_empty());
+    }
+
+    #[test]
+    fn it_overrides_a_variable() {
+        let mut bundle = Bundle::load(
+            r#"
+variables:
+  region: RegionOne
+applications:
+  keystone:
+    charm: cs:keystone
+    options:
+      region: $region
+"#,
+        )
+        .unwrap();
+        let overlay = Overlay::parse("variables: {region: RegionTwo}").unwrap();
+        bundle.apply_overlay(&overlay).unwrap();
+        let keystone = bundle.application("keystone").unwrap();
+        assert_eq!(keystone.option("region").unwrap(), "RegionTwo");
+        assert!(bundle.variable_issues().is_empty());
+    }
+
+    #[test]
+    fn it_substitutes_variables() {
+        let mut bundle = Bundle::load(
+            r#"
+variables:
+  workers: 0.5
+applications:
+  keystone:
+    charm: cs:keystone
+    options:
+      worker-multiplier: $workers
+      vip: $vip
+"#,
+        )
+        .unwrap();
+        assert_eq!(bundle.variable_issues().len(), 1);
+        let overlay = Overlay::parse(
+            r#"
+variables:
+  vip: 10.0.0.10
+  region: RegionTwo
+applications:
+  keystone:
+    options:
+      region: $region
+"#,
+        )
+        .unwrap();
+        bundle.apply_overlay(&overlay).unwrap();
+        let keystone = bundle.application("keystone").unwrap();
+        assert_eq!(keystone.option("worker-multiplier").unwrap(), 0.5);
+        assert_eq!(keystone.option("vip").unwrap(), "10.0.0.10");
+        assert_eq!(keystone.option("region").unwrap(), "RegionTwo");
+        assert!(bundle.variable_issues().is_empty());
+    }
+
+    #[test]
+    fn it_rejects_unknown_keys() {
+        let error = |overlay| Overlay::parse(overlay).unwrap_err().to_string();
+        assert_eq!(
+            error("applications: {keystone: {num-units: 3}}"),
+            "Unknown key num-units in application keystone"
+        );
+        assert_eq!(
+            error("aplications: {}"),
+            "Unknown key aplications in the overlay"
+        );
+    }
+
+    #[test]
+    fn it_ignores_keys_it_does_not_merge() {
+        let overlay = Overlay::parse(
+            r#"
+description: HA
+series: focal
+saas:
+  prometheus:
+    url: ctrl:admin/prometheus
+applications:
+  keystone:
+    constraints: mem=4G
+    expose: true
+    trust: true
+    num_units: 3
+"#,
+        )
+        .unwrap();
+        let mut bundle = Bundle::load(BUNDLE).unwrap();
+        bundle.apply_overlay(&overlay).unwrap();
+        assert_eq!(bundle.application("keystone").unwrap().num_units(), 3);
+    }
+
+    #[test]
+    fn it_needs_a_charm_for_a_new_application() {
+        let mut bundle = Bundle::load(BUNDLE).unwrap();
+        let overlay = Overlay::parse("applications: {glance: {num_units: 1}}").unwrap();
+        assert!(bundle.apply_overlay(&overlay).is_err());
+    }
+}
+
+/// The keys Juju accepts at the top of an overlay that aren't merged.
+const IGNORED_KEYS: &[&str] = &[
+    "bundle",
+    "default-base",
+    "description",
+    "saas",
+    "series",
+    "type",
+];
+
+/// The keys Juju accepts in an application that aren't merged.
+const IGNORED_APPLICATION_KEYS: &[&str] = &[
+    "annotations",
+    "base",
+    "channel",
+    "constraints",
+    "devices",
+    "expose",
+    "exposed-endpoints",
+    "offers",
+    "placement",
+    "plan",
+    "requires",
+    "resources",
+    "revision",
+    "scale",
+    "series",
+    "storage",
+    "trust",
+];
+
+/// Changes to an application made by an overlay. Anything left out keeps
+/// the bundle's value.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub(crate) struct ApplicationOverlay {
+    #[serde(default)]
+    pub(crate) charm: Option<String>,
+    #[serde(default)]
+    pub(crate) num_units: Option<usize>,
+    #[serde(default)]
+    pub(crate) to: Option<Vec<String>>,
+    #[serde(default)]
+    pub(crate) options: BTreeMap<String, serde_yaml::Value>,
+    #[serde(default)]
+    pub(crate) bindings: BTreeMap<String, String>,
+    /// Other keys, which must be in [`IGNORED_APPLICATION_KEYS`].
+    #[serde(flatten)]
+    pub(crate) ignored: BTreeMap<String, serde_yaml::Value>,
+}
+
+/// A bundle overlay, as given to `juju deploy --overlay`.
+///
+/// Applications and machines set to `null` are removed from the bundle,
+/// along with the relations of removed applications. Relations are added,
+/// and `variables` are merged into the bundle's, replacing any of the same
+/// name, before `$name` references are substituted again.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Overlay {
+    #[serde(default)]
+    pub(crate) variables: BTreeMap<String, serde_yaml::Value>,
+    #[serde(default)]
+    pub(crate) applications: BTreeMap<String, Option<ApplicationOverlay>>,
+    #[serde(default)]
+    pub(crate) machines: BTreeMap<String, Option<Machine>>,
+    #[serde(default)]
+    pub(crate) relations: Vec<[String; 2]>,
+    /// Other keys, which must be in [`IGNORED_KEYS`].
+    #[serde(flatten)]
+    pub(crate) ignored: BTreeMap<String, serde_yaml::Value>,
+    /// The file the overlay was loaded from.
+    #[serde(skip)]
+    pub(crate) path: Option<String>,
+    /// The directory include directives are resolved relative to.
+    #[serde(skip)]
+    pub(crate) base: Option<PathBuf>,
+}
+
+impl Overlay {
+    /// Parses an overlay. Keys that Juju accepts but that aren't merged,
+    /// such as `constraints`, are ignored, and any other key is an error.
+    pub fn parse(input: &str) -> Result<Overlay, Error> {
+        let overlay: Overlay = serde_yaml::from_str(input)?;
+        if let Some(key) = unknown(&overlay.ignored, IGNORED_KEYS) {
+            return Err(format_err!("Unknown key {} in the overlay", key));
+        }
+        for (name, application) in &overlay.applications {
+            if let Some(application) = application {
+                if let Some(key) = unknown(&application.ignored, IGNORED_APPLICATION_KEYS) {
+                    return Err(format_err!("Unknown key {} in application {}", key, name));
+                }
+            }
+        }
+        Ok(overlay)
+    }
+
+    /// The file the overlay was loaded from, for reporting errors.
+    pub fn path(&self) -> &str {
+        self.path.as_deref().unwrap_or("the overlay")
+    }
+
+    /// Loads an overlay from `path`, resolving its include directives
+    /// relative to the directory containing it.
+    pub fn load(path: PathBuf) -> Result<Overlay, JujuLintError> {
+        let display = path.display().to_string();
+        let input = fs::read_to_string(&path).map_err(|e| JujuLintError::BundleIo {
+            path: display.clone(),
+            reason: e.to_string(),
+        })?;
+        let mut overlay = Overlay::parse(&input).map_err(|e| JujuLintError::BundleParse {
+            path: display.clone(),
+            reason: e.to_string(),
+        })?;
+        overlay.path = Some(display);
+        overlay.base = Some(match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => env::current_dir().map_err(|e| JujuLintError::BundleIo {
+                path: path.display().to_string(),
+                reason: e.to_string(),
+            })?,
+        });
+        Ok(overlay)
+    }
+}
+
+/// The first key that isn't one of the `known` keys.
+fn unknown<'a>(keys: &'a BTreeMap<String, serde_yaml::Value>, known: &[&str]) -> Option<&'a str> {
+    keys.keys()
+        .map(|key| key.as_str())
+        .find(|key| !known.contains(key))
+}
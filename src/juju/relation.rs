@@ -0,0 +1,110 @@
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_an_endpoint() {
+        let end: RelationEndpoint = "neutron-api:neutron-plugin-api".parse().unwrap();
+        assert_eq!(end.application, "neutron-api");
+        assert_eq!(end.endpoint.as_ref().unwrap(), "neutron-plugin-api");
+        let end: RelationEndpoint = "neutron-api".parse().unwrap();
+        assert_eq!(end.endpoint, None);
+    }
+
+    #[test]
+    fn it_matches_an_omitted_endpoint() {
+        let full = RelationEndpoint::new("neutron-api", Some("neutron-plugin-api"));
+        let bare = RelationEndpoint::new("neutron-api", None);
+        let other = RelationEndpoint::new("neutron-api", Some("shared-db"));
+        assert!(full.matches(&bare));
+        assert!(bare.matches(&full));
+        assert!(!full.matches(&other));
+        assert!(!full.matches(&RelationEndpoint::new("nova-compute", None)));
+    }
+
+    #[test]
+    fn it_matches_pairs_in_either_order() {
+        let rule = [
+            RelationEndpoint::new("neutron-api", Some("neutron-plugin-api")),
+            RelationEndpoint::new("neutron-openvswitch", Some("neutron-plugin-api")),
+        ];
+        let bundle = [
+            RelationEndpoint::new("neutron-openvswitch", None),
+            RelationEndpoint::new("neutron-api", None),
+        ];
+        assert!(pair_matches(&rule, &bundle));
+    }
+}
+
+/// One end of a relation: an application and, optionally, the endpoint
+/// used on it.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct RelationEndpoint {
+    pub application: String,
+    pub endpoint: Option<String>,
+}
+
+impl RelationEndpoint {
+    pub fn new(application: &str, endpoint: Option<&str>) -> RelationEndpoint {
+        RelationEndpoint {
+            application: application.to_string(),
+            endpoint: endpoint.map(|e| e.to_string()),
+        }
+    }
+
+    /// Whether two ends refer to the same application and endpoint. An
+    /// omitted endpoint on either side matches any endpoint.
+    pub fn matches(&self, other: &RelationEndpoint) -> bool {
+        self.application == other.application
+            && match (&self.endpoint, &other.endpoint) {
+                (Some(a), Some(b)) => a == b,
+                _ => true,
+            }
+    }
+}
+
+/// Whether two relations match, in either order.
+pub fn pair_matches(a: &[RelationEndpoint; 2], b: &[RelationEndpoint; 2]) -> bool {
+    (a[0].matches(&b[0]) && a[1].matches(&b[1])) || (a[0].matches(&b[1]) && a[1].matches(&b[0]))
+}
+
+impl FromStr for RelationEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RelationEndpoint, String> {
+        let mut parts = s.splitn(2, ':');
+        let application = parts.next().unwrap_or_default();
+        if application.is_empty() {
+            return Err(format!("{:?} has no application name", s));
+        }
+        Ok(RelationEndpoint::new(application, parts.next()))
+    }
+}
+
+impl fmt::Display for RelationEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.endpoint {
+            Some(endpoint) => write!(f, "{}:{}", self.application, endpoint),
+            None => write!(f, "{}", self.application),
+        }
+    }
+}
+
+impl Serialize for RelationEndpoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RelationEndpoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<RelationEndpoint, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use failure::Error;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = include_str!("../../tests/status/openstack.yaml");
+
+    #[test]
+    fn it_parses_a_status() {
+        let status = Status::load(STATUS).unwrap();
+        assert_eq!(status.model.version.as_deref(), Some("2.9.42"));
+        let keystone = &status.applications["keystone"];
+        assert_eq!(keystone.charm_rev, Some(309));
+        let unit = &keystone.units["keystone/0"];
+        assert_eq!(unit.workload_status.current.as_deref(), Some("error"));
+        assert_eq!(unit.machine.as_deref(), Some("0/lxd/0"));
+    }
+
+    #[test]
+    fn it_parses_json() {
+        let status = Status::load(
+            r#"{"model": {"name": "openstack"},
+                "machines": {"0": {"base": {"name": "ubuntu", "channel": "22.04"}}},
+                "applications": {"keystone": {"charm-rev": 309, "units": {
+                    "keystone/0": {"machine": "0"}}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            status.machines["0"].series().as_deref(),
+            Some("ubuntu@22.04")
+        );
+    }
+
+    #[test]
+    fn it_finds_containers() {
+        let status = Status::load(STATUS).unwrap();
+        assert_eq!(
+            status.machine("0/lxd/0").unwrap().series().as_deref(),
+            Some("focal")
+        );
+        assert!(status.machine("0/lxd/1").is_none());
+        assert!(status.machine("2").is_none());
+    }
+}
+
+/// The output of `juju status --format yaml`, or `--format json`, with the
+/// fields that lints look at.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Status {
+    #[serde(default)]
+    pub model: ModelStatus,
+    #[serde(default)]
+    pub machines: BTreeMap<String, MachineStatus>,
+    #[serde(default)]
+    pub applications: BTreeMap<String, ApplicationStatus>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ModelStatus {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub controller: Option<String>,
+    /// The model's agent version.
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+/// The `current` value of a status, its message, and for agents, their
+/// version.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct StatusInfo {
+    #[serde(default)]
+    pub current: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+}
+
+impl StatusInfo {
+    pub fn is(&self, current: &str) -> bool {
+        self.current.as_deref() == Some(current)
+    }
+}
+
+/// A Juju 3 base, e.g. `ubuntu` `22.04`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Base {
+    pub name: String,
+    pub channel: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MachineStatus {
+    #[serde(default)]
+    pub juju_status: StatusInfo,
+    #[serde(default)]
+    pub machine_status: StatusInfo,
+    #[serde(default)]
+    series: Option<String>,
+    #[serde(default)]
+    base: Option<Base>,
+    /// Containers on the machine, keyed by their full id, e.g. `0/lxd/1`.
+    #[serde(default)]
+    pub containers: BTreeMap<String, MachineStatus>,
+}
+
+impl MachineStatus {
+    /// The machine's series, or its base as `name@channel` on Juju 3.
+    pub fn series(&self) -> Option<String> {
+        match (&self.series, &self.base) {
+            (Some(series), _) => Some(series.clone()),
+            (None, Some(base)) => Some(format!("{}@{}", base.name, base.channel)),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ApplicationStatus {
+    #[serde(default)]
+    pub charm: Option<String>,
+    #[serde(default)]
+    pub charm_rev: Option<u64>,
+    #[serde(default)]
+    pub application_status: StatusInfo,
+    /// The units, keyed by name, e.g. `keystone/0`. Subordinates have none.
+    #[serde(default)]
+    pub units: BTreeMap<String, UnitStatus>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct UnitStatus {
+    #[serde(default)]
+    pub workload_status: StatusInfo,
+    #[serde(default)]
+    pub juju_status: StatusInfo,
+    /// The id of the machine or container the unit is on.
+    #[serde(default)]
+    pub machine: Option<String>,
+    #[serde(default)]
+    pub subordinates: BTreeMap<String, UnitStatus>,
+}
+
+impl Status {
+    /// Parses `juju status` output, in YAML or JSON.
+    pub fn load(input: &str) -> Result<Status, Error> {
+        Ok(serde_yaml::from_str(input)?)
+    }
+
+    /// Finds a machine or container by id, e.g. `0` or `0/lxd/1`.
+    pub fn machine(&self, id: &str) -> Option<&MachineStatus> {
+        let parts: Vec<_> = id.split('/').collect();
+        let mut machine = self.machines.get(parts[0])?;
+        for end in (3..=parts.len()).step_by(2) {
+            machine = machine.containers.get(&parts[..end].join("/"))?;
+        }
+        Some(machine)
+    }
+}
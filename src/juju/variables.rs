@@ -0,0 +1,69 @@
+use std::fmt;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_recognises_a_reference() {
+        assert_eq!(reference(&"$worker_mult".into()), Some("worker_mult"));
+        assert_eq!(reference(&"$data-port".into()), Some("data-port"));
+    }
+
+    #[test]
+    fn it_ignores_other_values() {
+        assert_eq!(reference(&"br-ex:$port".into()), None);
+        assert_eq!(reference(&"$".into()), None);
+        assert_eq!(reference(&"$1.50".into()), None);
+        assert_eq!(reference(&0.25.into()), None);
+    }
+}
+
+/// A problem found while substituting bundle `variables`.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum VariableIssue {
+    /// An option references a variable that isn't defined.
+    Undefined {
+        application: String,
+        option: String,
+        variable: String,
+    },
+    /// A variable is defined but never referenced.
+    Unused { variable: String },
+}
+
+impl fmt::Display for VariableIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VariableIssue::Undefined {
+                application,
+                option,
+                variable,
+            } => write!(
+                f,
+                "{} / {} references undefined variable ${}",
+                application, option, variable
+            ),
+            VariableIssue::Unused { variable } => {
+                write!(f, "Variable ${} is defined but never used", variable)
+            }
+        }
+    }
+}
+
+/// The name of the variable referenced by `value`, if it is a `$name`
+/// reference. Juju only substitutes whole values, so `$name` embedded in a
+/// longer string is left alone.
+pub(crate) fn reference(value: &serde_yaml::Value) -> Option<&str> {
+    let name = value.as_str()?.strip_prefix('$')?;
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return None,
+    }
+    if chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        Some(name)
+    } else {
+        None
+    }
+}
@@ -1,20 +1,24 @@
+// `failure_derive` generates its impls inside an anonymous const.
+#![allow(non_local_definitions)]
+
 #[macro_use]
 extern crate failure;
 
+pub mod charm;
+mod error;
 pub(crate) mod fetch;
 pub mod juju;
+pub mod lint;
 mod rule;
 
+pub use error::JujuLintError;
+pub use lint::{
+    Check, CheckMetadata, Finding, LintContext, Linter, LinterBuilder, ModelReport, Report,
+    Severity, Suppression,
+};
 pub use rule::import as import_rules;
-pub use rule::{Rule, VerificationResult};
-
-// This is a new error type that you've created. It represents the ways a
-// toolchain could be invalid.
-//
-// The custom derive for Fail derives an impl of both Fail and Display.
-// We don't do any other magic like creating new types.
-#[derive(Debug, Fail)]
-pub enum JujuLintError {
-    #[fail(display = "Bundle failed lint")]
-    LintFailure,
-}
+pub use rule::{
+    Agreement, ApplicationCondition, Binding, ChangeCondition, Comparison, Condition, Config,
+    ConfigCondition, ConfigValue, Expected, Expression, OptionReference, Pattern, Predicate,
+    Relation, Rule, StatusCondition, Subject, Subordinate, Units, VerificationResult,
+};
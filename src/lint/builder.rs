@@ -0,0 +1,108 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use crate::charm::SchemaStore;
+use crate::juju::Overlay;
+use crate::lint::{Check, Linter, Severity, Suppression};
+use crate::rule::{import, Rule};
+use crate::JujuLintError;
+
+/// Configures a [`Linter`](crate::Linter). Rule sources and overlays are
+/// loaded by [`build`](LinterBuilder::build).
+#[derive(Default)]
+pub struct LinterBuilder {
+    sources: Vec<String>,
+    rules: Vec<Rule>,
+    checks: Vec<Box<dyn Check>>,
+    overlays: Vec<PathBuf>,
+    charm_schemas: Vec<PathBuf>,
+    threshold: Option<Severity>,
+    suppressions: Vec<Suppression>,
+    enabled: BTreeSet<String>,
+    disabled: BTreeSet<String>,
+}
+
+impl LinterBuilder {
+    /// Loads rules from a local directory, a git URL or `gh:owner/repo`.
+    pub fn rule_source(mut self, source: &str) -> LinterBuilder {
+        self.sources.push(source.to_string());
+        self
+    }
+
+    pub fn rules(mut self, rules: Vec<Rule>) -> LinterBuilder {
+        self.rules.extend(rules);
+        self
+    }
+
+    pub fn check(mut self, check: Box<dyn Check>) -> LinterBuilder {
+        self.checks.push(check);
+        self
+    }
+
+    /// Merges an overlay into each bundle before it is linted.
+    pub fn overlay(mut self, path: PathBuf) -> LinterBuilder {
+        self.overlays.push(path);
+        self
+    }
+
+    /// Enables the `charm-config` and `charm-relations` checks with the
+    /// schemas under `path`.
+    pub fn charm_schemas(mut self, path: PathBuf) -> LinterBuilder {
+        self.charm_schemas.push(path);
+        self
+    }
+
+    /// Leaves findings below `severity` out of the report.
+    pub fn min_severity(mut self, severity: Severity) -> LinterBuilder {
+        self.threshold = Some(severity);
+        self
+    }
+
+    pub fn suppress(mut self, suppression: Suppression) -> LinterBuilder {
+        self.suppressions.push(suppression);
+        self
+    }
+
+    /// Runs only the enabled checks, once any check is enabled.
+    pub fn enable(mut self, id: &str) -> LinterBuilder {
+        self.enabled.insert(id.to_string());
+        self
+    }
+
+    pub fn disable(mut self, id: &str) -> LinterBuilder {
+        self.disabled.insert(id.to_string());
+        self
+    }
+
+    pub fn build(self) -> Result<Linter, JujuLintError> {
+        let mut linter = Linter::new();
+        for source in &self.sources {
+            linter.add_rules(import(source)?);
+        }
+        linter.add_rules(self.rules);
+        for check in self.checks {
+            linter.register(check);
+        }
+        let mut ids = BTreeSet::new();
+        for check in linter.checks() {
+            if !ids.insert(check.id()) {
+                return Err(JujuLintError::DuplicateCheck {
+                    id: check.id().to_string(),
+                });
+            }
+        }
+        for path in self.overlays {
+            linter.overlays.push(Overlay::load(path)?);
+        }
+        if !self.charm_schemas.is_empty() {
+            linter.schemas = Some(SchemaStore::new(self.charm_schemas));
+        }
+        if let Some(threshold) = self.threshold {
+            linter.threshold = threshold;
+        }
+        linter.suppressions = self.suppressions;
+        linter.enabled = self.enabled;
+        linter.disabled = self.disabled;
+        Ok(linter)
+    }
+}
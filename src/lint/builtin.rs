@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+
+use crate::charm::OptionIssue;
+use crate::juju::{MachineStatus, VariableIssue};
+use crate::lint::{Check, CheckMetadata, Finding, LintContext, Severity};
+use crate::rule::{Rule, VerificationResult};
+
+impl Check for Rule {
+    fn id(&self) -> &str {
+        Rule::id(self)
+    }
+
+    fn metadata(&self) -> CheckMetadata {
+        CheckMetadata {
+            description: format!("YAML rule {}", self.display_name()),
+        }
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<Finding> {
+        let mut results = vec![self.verify_with_schemas(context.bundle(), context.schemas())];
+        if let Some(status) = context.status() {
+            results.push(self.verify_status(status));
+        }
+        if let Some(previous) = context.previous() {
+            results.push(self.verify_change(previous, context.bundle()));
+        }
+        results
+            .into_iter()
+            .filter_map(|result| match result {
+                VerificationResult::Pass => None,
+                VerificationResult::Fail { reason } => {
+                    let finding = Finding::new(self.id(), reason).with_severity(self.severity);
+                    if self.charm_name.is_empty() {
+                        Some(finding)
+                    } else {
+                        Some(finding.with_application(&self.charm_name))
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Reports undefined and unused bundle variables.
+pub struct Variables;
+
+impl Check for Variables {
+    fn id(&self) -> &str {
+        "bundle-variables"
+    }
+
+    fn metadata(&self) -> CheckMetadata {
+        CheckMetadata {
+            description: "Bundle variables are defined and used".to_string(),
+        }
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<Finding> {
+        context
+            .bundle()
+            .variable_issues()
+            .iter()
+            .map(|issue| {
+                let finding = Finding::new(self.id(), issue.to_string());
+                match issue {
+                    VariableIssue::Undefined { application, .. } => {
+                        finding.with_application(application)
+                    }
+                    VariableIssue::Unused { .. } => finding,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Checks options against the charms' config schemas.
+pub struct CharmConfig;
+
+impl Check for CharmConfig {
+    fn id(&self) -> &str {
+        "charm-config"
+    }
+
+    fn metadata(&self) -> CheckMetadata {
+        CheckMetadata {
+            description: "Options exist in the charm's config.yaml and have its types".to_string(),
+        }
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<Finding> {
+        let schemas = match context.schemas() {
+            Some(schemas) => schemas,
+            None => return vec![],
+        };
+        match schemas.verify_options(context.bundle()) {
+            Ok(issues) => issues
+                .iter()
+                .map(|issue| {
+                    let application = match issue {
+                        OptionIssue::Unknown { application, .. }
+                        | OptionIssue::WrongType { application, .. } => application,
+                    };
+                    Finding::new(self.id(), issue.to_string()).with_application(application)
+                })
+                .collect(),
+            Err(e) => vec![Finding::new(
+                self.id(),
+                format!("Failed to load the charm schemas: {}", e),
+            )],
+        }
+    }
+}
+
+/// Checks relations against the charms' metadata.
+pub struct CharmRelations;
+
+impl Check for CharmRelations {
+    fn id(&self) -> &str {
+        "charm-relations"
+    }
+
+    fn metadata(&self) -> CheckMetadata {
+        CheckMetadata {
+            description: "Relations join compatible endpoints from the charms' metadata.yaml"
+                .to_string(),
+        }
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<Finding> {
+        let schemas = match context.schemas() {
+            Some(schemas) => schemas,
+            None => return vec![],
+        };
+        match schemas.verify_relations(context.bundle()) {
+            Ok(issues) => issues
+                .iter()
+                .map(|issue| Finding::new(self.id(), issue.to_string()))
+                .collect(),
+            Err(e) => vec![Finding::new(
+                self.id(),
+                format!("Failed to load the charm schemas: {}", e),
+            )],
+        }
+    }
+}
+
+/// Reports units and machines in error in `juju status`.
+pub struct StatusErrors;
+
+impl Check for StatusErrors {
+    fn id(&self) -> &str {
+        "status-errors"
+    }
+
+    fn metadata(&self) -> CheckMetadata {
+        CheckMetadata {
+            description: "No unit or machine is in error, lost or down".to_string(),
+        }
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<Finding> {
+        let status = match context.status() {
+            Some(status) => status,
+            None => return vec![],
+        };
+        let mut findings = vec![];
+        for (name, application) in &status.applications {
+            for (unit_name, unit) in &application.units {
+                let units = Some((unit_name, unit))
+                    .into_iter()
+                    .chain(&unit.subordinates);
+                for (unit_name, unit) in units {
+                    if unit.workload_status.is("error") {
+                        let message = match &unit.workload_status.message {
+                            Some(message) => format!("{} is in error: {}", unit_name, message),
+                            None => format!("{} is in error", unit_name),
+                        };
+                        findings.push(Finding::new(self.id(), message).with_application(name));
+                    }
+                    if unit.juju_status.is("lost") || unit.juju_status.is("error") {
+                        findings.push(
+                            Finding::new(
+                                self.id(),
+                                format!(
+                                    "{} agent is {}",
+                                    unit_name,
+                                    unit.juju_status.current.as_deref().unwrap_or_default()
+                                ),
+                            )
+                            .with_application(name),
+                        );
+                    }
+                }
+            }
+        }
+        for (id, machine) in &status.machines {
+            self.check_machine(id, machine, &mut findings);
+        }
+        findings
+    }
+}
+
+impl StatusErrors {
+    fn check_machine(&self, id: &str, machine: &MachineStatus, findings: &mut Vec<Finding>) {
+        if machine.juju_status.is("down") {
+            findings.push(Finding::new(self.id(), format!("machine {} is down", id)));
+        }
+        if let Some(current) = &machine.machine_status.current {
+            if current.contains("error") {
+                findings.push(Finding::new(
+                    self.id(),
+                    format!(
+                        "machine {} has {}: {}",
+                        id,
+                        current,
+                        machine
+                            .machine_status
+                            .message
+                            .as_deref()
+                            .unwrap_or_default()
+                    ),
+                ));
+            }
+        }
+        for (id, container) in &machine.containers {
+            self.check_machine(id, container, findings);
+        }
+    }
+}
+
+/// Reports where `juju status` differs from the bundle: applications that
+/// aren't deployed or aren't in the bundle, unit counts, charm revisions
+/// and machine series.
+pub struct StatusDrift;
+
+impl Check for StatusDrift {
+    fn id(&self) -> &str {
+        "status-drift"
+    }
+
+    fn metadata(&self) -> CheckMetadata {
+        CheckMetadata {
+            description: "The deployed model matches the bundle".to_string(),
+        }
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<Finding> {
+        let status = match context.status() {
+            Some(status) => status,
+            None => return vec![],
+        };
+        let bundle = context.bundle();
+        let finding =
+            |message: String| Finding::new(self.id(), message).with_severity(Severity::Warning);
+        let mut findings = vec![];
+        let mut names: Vec<_> = bundle.applications.keys().collect();
+        names.sort();
+        for name in names {
+            let application = &bundle.applications[name];
+            let deployed = match status.applications.get(name) {
+                Some(deployed) => deployed,
+                None => {
+                    findings.push(
+                        finding(format!("{} is in the bundle but not deployed", name))
+                            .with_application(name),
+                    );
+                    continue;
+                }
+            };
+            // Subordinates have no units in either.
+            if deployed.units.len() != application.num_units() {
+                findings.push(
+                    finding(format!(
+                        "{} has {} units deployed, the bundle has {}",
+                        name,
+                        deployed.units.len(),
+                        application.num_units()
+                    ))
+                    .with_application(name),
+                );
+            }
+            if let (Some(pinned), Some(running)) = (application.revision(), deployed.charm_rev) {
+                if pinned != running {
+                    findings.push(
+                        finding(format!(
+                            "{} runs revision {} of its charm, the bundle pins {}",
+                            name, running, pinned
+                        ))
+                        .with_application(name),
+                    );
+                }
+            }
+        }
+        for name in status.applications.keys() {
+            if !bundle.applications.contains_key(name) {
+                findings.push(
+                    finding(format!("{} is deployed but not in the bundle", name))
+                        .with_application(name),
+                );
+            }
+        }
+        // Deployed machine ids needn't match the bundle's, so each is mapped
+        // to the bundle machine its units are placed on.
+        let mut placed = BTreeMap::new();
+        for (name, deployed) in &status.applications {
+            for (unit, unit_status) in &deployed.units {
+                let index = unit.rsplit('/').next().and_then(|i| i.parse().ok());
+                let target = index.and_then(|index| bundle.unit_machine(name, index));
+                if let (Some(target), Some(machine)) = (target, &unit_status.machine) {
+                    let id = machine.split('/').next().unwrap_or_default();
+                    placed.insert(id, target);
+                }
+            }
+        }
+        for (id, machine) in &status.machines {
+            let expected = placed
+                .get(id.as_str())
+                .and_then(|target| bundle.machines.get(*target))
+                .and_then(|m| m.series())
+                .or(bundle.series.as_deref());
+            // Juju 3 machines have a base rather than a series, which
+            // bundles with a series can't be compared with.
+            match (machine.series(), expected) {
+                (Some(series), Some(expected)) if !series.contains('@') && series != expected => {
+                    findings.push(finding(format!(
+                        "machine {} runs {}, the bundle expects {}",
+                        id, series, expected
+                    )))
+                }
+                _ => {}
+            }
+        }
+        findings
+    }
+}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::lint::LintContext;
+
+/// How serious a finding is.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    #[default]
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// Describes a check to people reading its findings.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct CheckMetadata {
+    pub description: String,
+}
+
+/// A problem a check found in a bundle.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Finding {
+    /// The id of the check that found it.
+    pub check: String,
+    pub severity: Severity,
+    /// The application it concerns, if it concerns a single one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub application: Option<String>,
+    pub message: String,
+}
+
+impl Finding {
+    pub fn new(check: &str, message: String) -> Finding {
+        Finding {
+            check: check.to_string(),
+            severity: Severity::default(),
+            application: None,
+            message,
+        }
+    }
+
+    pub fn with_application(mut self, application: &str) -> Finding {
+        self.application = Some(application.to_string());
+        self
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Finding {
+        self.severity = severity;
+        self
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.check, self.message)
+    }
+}
+
+/// A lint that can be run against a bundle.
+///
+/// Implement this to add checks from Rust, and register them with
+/// [`Linter::register`](crate::Linter::register). Checks are shared between
+/// the threads that lint several models at once.
+pub trait Check: Send + Sync {
+    /// A stable, unique id, used to report findings and to enable or
+    /// disable the check.
+    fn id(&self) -> &str;
+
+    fn metadata(&self) -> CheckMetadata {
+        CheckMetadata::default()
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<Finding>;
+}
@@ -0,0 +1,587 @@
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::charm::SchemaStore;
+use crate::juju::{is_unreadable, Bundle, Juju, Model, Overlay, Status};
+use crate::rule::Rule;
+use crate::JujuLintError;
+
+mod builder;
+mod builtin;
+mod check;
+mod report;
+
+pub use builder::LinterBuilder;
+pub use builtin::{CharmConfig, CharmRelations, StatusDrift, StatusErrors, Variables};
+pub use check::{Check, CheckMetadata, Finding, Severity};
+pub use report::{ModelReport, Report, Suppression};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct SingleUnit;
+
+    impl Check for SingleUnit {
+        fn id(&self) -> &str {
+            "single-unit"
+        }
+
+        fn check(&self, context: &LintContext) -> Vec<Finding> {
+            let mut names: Vec<_> = context.bundle().applications.keys().collect();
+            names.sort();
+            names
+                .into_iter()
+                .filter(|name| context.bundle().applications[*name].num_units() == 1)
+                .map(|name| {
+                    Finding::new(self.id(), format!("{} has a single unit", name))
+                        .with_application(name)
+                        .with_severity(Severity::Warning)
+                })
+                .collect()
+        }
+    }
+
+    const BUNDLE: &str = r#"
+variables:
+  unused: 1
+applications:
+  keystone:
+    charm: cs:keystone
+    num_units: 1
+"#;
+
+    #[test]
+    fn it_runs_rules_and_registered_checks() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let rules: Vec<Rule> = serde_yaml::from_str(
+            r#"- charm_name: keystone
+  units:
+    - min: 3"#,
+        )
+        .unwrap();
+        let mut linter = Linter::new();
+        linter.add_rules(rules);
+        linter.register(Box::new(SingleUnit));
+        let findings = linter.run(&LintContext::new(&bundle));
+        let messages: Vec<_> = findings.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "bundle-variables: Variable $unused is defined but never used",
+                "rules#1: keystone has 1 units, requires at least 3",
+                "single-unit: keystone has a single unit",
+            ]
+        );
+        assert_eq!(findings[2].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn it_builds_a_configured_linter() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let rules: Vec<Rule> = serde_yaml::from_str(
+            r#"- name: ha-keystone
+  charm_name: keystone
+  severity: warning
+  units:
+    - min: 3
+- name: keystone-debug
+  charm_name: keystone
+  config:
+    - config_name: debug
+      config_value: true
+      requires:
+        keystone:
+          - name: log-level"#,
+        )
+        .unwrap();
+        let linter = Linter::builder()
+            .rules(rules)
+            .check(Box::new(SingleUnit))
+            .overlay(
+                std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("tests/overlays/debug.yaml"),
+            )
+            .min_severity(Severity::Error)
+            .suppress(Suppression::new("bundle-variables"))
+            .disable("single-unit")
+            .build()
+            .unwrap();
+        let report = linter.lint(&bundle).unwrap();
+        let messages: Vec<_> = report.findings.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec!["keystone-debug: keystone / log-level has a missing config value"]
+        );
+        assert_eq!(report.below_threshold, 1);
+        assert_eq!(report.suppressed, 1);
+        assert!(!report.checks.contains(&"single-unit".to_string()));
+        assert!(serde_json::to_string(&report).is_ok());
+    }
+
+    #[test]
+    fn it_rejects_rules_with_the_same_name() {
+        let rules: Vec<Rule> = serde_yaml::from_str(
+            r#"- name: ha
+  charm_name: keystone
+  units:
+    - min: 3
+- name: ha
+  charm_name: glance
+  units:
+    - min: 3"#,
+        )
+        .unwrap();
+        match Linter::builder().rules(rules).build() {
+            Err(e) => assert_eq!(e.to_string(), "More than one check has the id ha"),
+            Ok(_) => panic!("Expected the duplicate name to be rejected"),
+        }
+    }
+
+    #[test]
+    fn it_tells_unnamed_rules_apart() {
+        let rules: Vec<Rule> = serde_yaml::from_str(
+            r#"- charm_name: keystone
+  units:
+    - min: 3
+- charm_name: keystone
+  units:
+    - max: 0"#,
+        )
+        .unwrap();
+        let linter = Linter::builder()
+            .rules(rules)
+            .disable("rules#1")
+            .build()
+            .unwrap();
+        let report = linter.lint(&Bundle::load(BUNDLE).unwrap()).unwrap();
+        let checks: Vec<_> = report.findings.iter().map(|f| f.check.as_str()).collect();
+        assert_eq!(checks, vec!["bundle-variables", "rules#2"]);
+    }
+
+    #[test]
+    fn it_names_unnamed_rules_by_source() {
+        let rules = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/rules");
+        let linter = Linter::builder()
+            .rule_source(&rules.join("unnamed").display().to_string())
+            .rule_source(&rules.join("more").display().to_string())
+            .build()
+            .unwrap();
+        let ids: Vec<_> = linter.checks()[5..]
+            .iter()
+            .map(|check| check.id().strip_prefix(rules.to_str().unwrap()).unwrap())
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                "/unnamed/keystone.yaml#1",
+                "/unnamed/keystone.yaml#2",
+                "/more/keystone.yaml#1"
+            ]
+        );
+
+        let mut linter = Linter::new();
+        let rule = || serde_yaml::from_str::<Vec<Rule>>("- charm_name: keystone").unwrap();
+        linter.add_rules(rule());
+        linter.add_rules(rule());
+        let ids: Vec<_> = linter.checks()[5..].iter().map(|c| c.id()).collect();
+        assert_eq!(ids, vec!["rules#1", "rules#2"]);
+    }
+
+    #[test]
+    fn it_lints_every_model_and_keeps_going() {
+        let juju = Juju::new(
+            std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/juju/fake-juju"),
+        );
+        let models = Model::list(&juju, None).unwrap();
+        let reports = Linter::new().lint_models(&juju, &models);
+        assert_eq!(reports[0].model, "ctrl:admin/openstack");
+        assert!(reports[0].passed());
+        assert_eq!(reports[1].model, "ctrl:admin/missing");
+        match &reports[1].error {
+            Some(JujuLintError::JujuCli { .. }) => {}
+            other => panic!("Expected the export to fail, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_lints_the_status() {
+        let bundle = Bundle::load(
+            r#"
+series: focal
+applications:
+  keystone:
+    charm: cs:keystone-312
+    num_units: 3
+"#,
+        )
+        .unwrap();
+        let status = Status::load(include_str!("../../tests/status/openstack.yaml")).unwrap();
+        let rules: Vec<Rule> = serde_yaml::from_str(
+            r#"- charm_name: keystone
+  status:
+    - agent_version: 2.9.42"#,
+        )
+        .unwrap();
+        let mut linter = Linter::new();
+        linter.add_rules(rules);
+        let report = linter.lint_with_status(&bundle, &status).unwrap();
+        let messages: Vec<_> = report.findings.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "status-errors: keystone/0 is in error: hook failed: \"config-changed\"",
+                "status-errors: machine 1 is down",
+                "status-errors: machine 1 has provisioning error: no matching node",
+                "status-drift: keystone has 2 units deployed, the bundle has 3",
+                "status-drift: keystone runs revision 309 of its charm, the bundle pins 312",
+                "status-drift: machine 1 runs bionic, the bundle expects focal",
+                "rules#1: keystone/1 agent version is \"2.9.38\", requires \"2.9.42\"",
+            ]
+        );
+        assert!(linter.lint(&bundle).unwrap().passed());
+    }
+
+    #[test]
+    fn it_compares_machines_through_unit_placement() {
+        let bundle = Bundle::load(
+            r#"
+machines:
+  "0":
+    series: bionic
+  "1":
+    series: focal
+applications:
+  keystone:
+    charm: cs:keystone-309
+    num_units: 2
+    to: ["lxd:1", "0"]
+"#,
+        )
+        .unwrap();
+        let status = Status::load(include_str!("../../tests/status/openstack.yaml")).unwrap();
+        let report = Linter::new().lint_with_status(&bundle, &status).unwrap();
+        let drift: Vec<_> = report
+            .findings
+            .iter()
+            .map(|f| f.to_string())
+            .filter(|f| f.starts_with("status-drift"))
+            .collect();
+        assert!(drift.is_empty(), "{:?}", drift);
+    }
+
+    #[test]
+    fn it_lints_a_change() {
+        let previous =
+            Bundle::load("applications: {ceph-mon: {charm: cs:ceph-mon, num_units: 3}}").unwrap();
+        let bundle =
+            Bundle::load("applications: {ceph-mon: {charm: cs:ceph-mon, num_units: 1}}").unwrap();
+        let rules: Vec<Rule> = serde_yaml::from_str(
+            r#"- name: ceph-mon-quorum
+  charm_name: ceph-mon
+  changes:
+    - num_units: true
+      require: new > old / 2"#,
+        )
+        .unwrap();
+        let mut linter = Linter::new();
+        linter.add_rules(rules);
+        let report = linter.lint_change(&previous, &bundle).unwrap();
+        let messages: Vec<_> = report.findings.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec!["ceph-mon-quorum: ceph-mon num_units changes from 3 to 1, requires new > old / 2"]
+        );
+        assert!(linter.lint(&bundle).unwrap().passed());
+    }
+
+    #[test]
+    fn it_merges_overlays_into_the_new_bundle_only() {
+        let previous = Bundle::load("applications: {keystone: {charm: cs:keystone}}").unwrap();
+        let rules: Vec<Rule> = serde_yaml::from_str(
+            r#"- name: no-debug
+  charm_name: keystone
+  changes:
+    - option: debug
+      require: new == null"#,
+        )
+        .unwrap();
+        let linter = Linter::builder()
+            .rules(rules)
+            .overlay(
+                std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("tests/overlays/debug.yaml"),
+            )
+            .build()
+            .unwrap();
+        let report = linter.lint_change(&previous, &previous).unwrap();
+        let messages: Vec<_> = report.findings.iter().map(|f| f.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec!["no-debug: keystone / debug changes from unset to true, requires new == null"]
+        );
+    }
+
+    #[test]
+    fn it_skips_schema_checks_without_schemas() {
+        let bundle = Bundle::load("applications: {keystone: {charm: cs:keystone}}").unwrap();
+        assert!(CharmConfig.check(&LintContext::new(&bundle)).is_empty());
+    }
+}
+
+/// What checks can look at: the bundle, and the charm schemas, `juju
+/// status` and the previous bundle if they were given.
+#[derive(Clone, Copy, Debug)]
+pub struct LintContext<'a> {
+    bundle: &'a Bundle,
+    schemas: Option<&'a SchemaStore>,
+    status: Option<&'a Status>,
+    previous: Option<&'a Bundle>,
+}
+
+impl<'a> LintContext<'a> {
+    pub fn new(bundle: &'a Bundle) -> LintContext<'a> {
+        LintContext {
+            bundle,
+            schemas: None,
+            status: None,
+            previous: None,
+        }
+    }
+
+    pub fn with_schemas(mut self, schemas: &'a SchemaStore) -> LintContext<'a> {
+        self.schemas = Some(schemas);
+        self
+    }
+
+    pub fn with_status(mut self, status: &'a Status) -> LintContext<'a> {
+        self.status = Some(status);
+        self
+    }
+
+    /// The bundle that `bundle` replaces, for checks on the change.
+    pub fn with_previous(mut self, previous: &'a Bundle) -> LintContext<'a> {
+        self.previous = Some(previous);
+        self
+    }
+
+    pub fn bundle(&self) -> &'a Bundle {
+        self.bundle
+    }
+
+    pub fn schemas(&self) -> Option<&'a SchemaStore> {
+        self.schemas
+    }
+
+    pub fn status(&self) -> Option<&'a Status> {
+        self.status
+    }
+
+    pub fn previous(&self) -> Option<&'a Bundle> {
+        self.previous
+    }
+}
+
+/// Runs the built-in checks, YAML rules and checks registered from Rust.
+pub struct Linter {
+    checks: Vec<Box<dyn Check>>,
+    schemas: Option<SchemaStore>,
+    overlays: Vec<Overlay>,
+    threshold: Severity,
+    suppressions: Vec<Suppression>,
+    enabled: BTreeSet<String>,
+    disabled: BTreeSet<String>,
+    /// How many rules without a source have been added.
+    unsourced: usize,
+}
+
+impl Default for Linter {
+    fn default() -> Linter {
+        Linter::new()
+    }
+}
+
+impl Linter {
+    /// A linter with the built-in checks.
+    pub fn new() -> Linter {
+        Linter {
+            checks: vec![
+                Box::new(Variables),
+                Box::new(CharmConfig),
+                Box::new(CharmRelations),
+                Box::new(StatusErrors),
+                Box::new(StatusDrift),
+            ],
+            schemas: None,
+            overlays: vec![],
+            threshold: Severity::Info,
+            suppressions: vec![],
+            enabled: BTreeSet::new(),
+            disabled: BTreeSet::new(),
+            unsourced: 0,
+        }
+    }
+
+    pub fn builder() -> LinterBuilder {
+        LinterBuilder::default()
+    }
+
+    /// Adds YAML rules as checks. Rules that weren't read from a file are
+    /// identified as `rules#1`, `rules#2` and so on when they have no name.
+    pub fn add_rules(&mut self, rules: Vec<Rule>) {
+        for mut rule in rules {
+            if rule.source.is_none() {
+                self.unsourced += 1;
+                rule.source = Some(format!("rules#{}", self.unsourced));
+            }
+            self.register(Box::new(rule));
+        }
+    }
+
+    pub fn register(&mut self, check: Box<dyn Check>) {
+        self.checks.push(check);
+    }
+
+    pub fn checks(&self) -> &[Box<dyn Check>] {
+        &self.checks
+    }
+
+    /// Runs every check in the order they were added.
+    pub fn run(&self, context: &LintContext) -> Vec<Finding> {
+        self.checks
+            .iter()
+            .flat_map(|check| check.check(context))
+            .collect()
+    }
+
+    fn is_enabled(&self, check: &dyn Check) -> bool {
+        (self.enabled.is_empty() || self.enabled.contains(check.id()))
+            && !self.disabled.contains(check.id())
+    }
+
+    /// Lints `bundle` with its overlays merged in, leaving out disabled
+    /// checks, suppressed findings and findings below the threshold.
+    pub fn lint(&self, bundle: &Bundle) -> Result<Report, JujuLintError> {
+        self.lint_with(bundle, None, None)
+    }
+
+    /// Lints `bundle` as [`lint`](Linter::lint) does, and the deployed model
+    /// in `status` with the status checks and rules.
+    pub fn lint_with_status(
+        &self,
+        bundle: &Bundle,
+        status: &Status,
+    ) -> Result<Report, JujuLintError> {
+        self.lint_with(bundle, Some(status), None)
+    }
+
+    /// Lints `bundle` as [`lint`](Linter::lint) does, and how it changes from
+    /// `previous` with the rules' change requirements.
+    pub fn lint_change(&self, previous: &Bundle, bundle: &Bundle) -> Result<Report, JujuLintError> {
+        self.lint_with(bundle, None, Some(previous))
+    }
+
+    /// Lints `bundle`, with `status` and `previous` if given. The overlays
+    /// are merged into `bundle` only: `previous` is what was deployed before,
+    /// so the change includes what the overlays change.
+    pub fn lint_with(
+        &self,
+        bundle: &Bundle,
+        status: Option<&Status>,
+        previous: Option<&Bundle>,
+    ) -> Result<Report, JujuLintError> {
+        let bundle = self.merge_overlays(bundle)?;
+        let mut context = LintContext::new(&bundle);
+        if let Some(schemas) = &self.schemas {
+            context = context.with_schemas(schemas);
+        }
+        if let Some(status) = status {
+            context = context.with_status(status);
+        }
+        if let Some(previous) = previous {
+            context = context.with_previous(previous);
+        }
+
+        let mut report = Report::default();
+        for check in self.checks.iter().filter(|c| self.is_enabled(c.as_ref())) {
+            report.checks.push(check.id().to_string());
+            for finding in check.check(&context) {
+                if finding.severity < self.threshold {
+                    report.below_threshold += 1;
+                } else if self.suppressions.iter().any(|s| s.matches(&finding)) {
+                    report.suppressed += 1;
+                } else {
+                    report.findings.push(finding);
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    fn merge_overlays<'a>(&self, bundle: &'a Bundle) -> Result<Cow<'a, Bundle>, JujuLintError> {
+        if self.overlays.is_empty() {
+            return Ok(Cow::Borrowed(bundle));
+        }
+        let mut merged = bundle.clone();
+        for overlay in &self.overlays {
+            merged.apply_overlay(overlay).map_err(|e| {
+                let path = overlay.path().to_string();
+                let reason = e.to_string();
+                if is_unreadable(&e) {
+                    JujuLintError::BundleIo { path, reason }
+                } else {
+                    JujuLintError::BundleParse { path, reason }
+                }
+            })?;
+        }
+        Ok(Cow::Owned(merged))
+    }
+
+    /// Exports and lints each of `models`, several at a time. A model that
+    /// can't be exported is reported with its error, and the rest are still
+    /// linted. Reports are in the order of `models`.
+    pub fn lint_models(&self, juju: &Juju, models: &[String]) -> Vec<ModelReport> {
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(models.len()));
+        let workers = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .min(models.len());
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let model = match models.get(index) {
+                        Some(model) => model,
+                        None => break,
+                    };
+                    let result =
+                        Model::export_bundle(juju, model).and_then(|bundle| self.lint(&bundle));
+                    results
+                        .lock()
+                        .expect("A linting thread panicked")
+                        .push((index, result));
+                });
+            }
+        });
+        let mut results = results.into_inner().expect("A linting thread panicked");
+        results.sort_by_key(|(index, _)| *index);
+        results
+            .into_iter()
+            .map(|(index, result)| {
+                let (report, error) = match result {
+                    Ok(report) => (Some(report), None),
+                    Err(error) => (None, Some(error)),
+                };
+                ModelReport {
+                    model: models[index].clone(),
+                    report,
+                    error,
+                }
+            })
+            .collect()
+    }
+}
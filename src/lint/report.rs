@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::lint::Finding;
+use crate::JujuLintError;
+
+/// Silences a check's findings, either everywhere or for one application.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Suppression {
+    pub check: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application: Option<String>,
+}
+
+impl Suppression {
+    pub fn new(check: &str) -> Suppression {
+        Suppression {
+            check: check.to_string(),
+            application: None,
+        }
+    }
+
+    pub fn for_application(mut self, application: &str) -> Suppression {
+        self.application = Some(application.to_string());
+        self
+    }
+
+    pub fn matches(&self, finding: &Finding) -> bool {
+        self.check == finding.check
+            && match &self.application {
+                Some(application) => finding.application.as_ref() == Some(application),
+                None => true,
+            }
+    }
+}
+
+/// The outcome of linting a bundle.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Report {
+    /// Findings at or above the severity threshold that weren't suppressed.
+    pub findings: Vec<Finding>,
+    /// The number of findings silenced by a suppression.
+    pub suppressed: usize,
+    /// The number of findings below the severity threshold.
+    pub below_threshold: usize,
+    /// The ids of the checks that ran.
+    pub checks: Vec<String>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// The outcome of linting one model of a controller.
+#[derive(Debug, Serialize)]
+pub struct ModelReport {
+    pub model: String,
+    /// The report, if the model's bundle could be exported and linted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<Report>,
+    /// Why the model couldn't be linted.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_error"
+    )]
+    pub error: Option<JujuLintError>,
+}
+
+impl ModelReport {
+    pub fn passed(&self) -> bool {
+        self.report.as_ref().is_some_and(Report::passed)
+    }
+}
+
+fn serialize_error<S: Serializer>(
+    error: &Option<JujuLintError>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match error {
+        Some(error) => serializer.serialize_str(&error.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
@@ -1,19 +1,56 @@
 extern crate bundle_lint;
 
-use failure::Error;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::str::FromStr;
 
 use log::{debug, Level};
 
+use structopt::clap::ErrorKind;
 use structopt::StructOpt;
 
 use bundle_lint::juju;
+use bundle_lint::{JujuLintError, Linter, ModelReport};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Opt {
+        Opt::from_iter_safe(Some("bundle-lint").iter().chain(args)).unwrap()
+    }
+
+    #[test]
+    fn it_takes_a_bundle_after_charm_schemas() {
+        let options = parse(&[
+            "--charm-schemas",
+            "charms",
+            "--charm-schemas",
+            "cache",
+            "bundle.yaml",
+        ]);
+        assert_eq!(
+            options.charm_schemas,
+            vec![PathBuf::from("charms"), PathBuf::from("cache")]
+        );
+        assert_eq!(options.bundle_path, Some(PathBuf::from("bundle.yaml")));
+    }
+
+    #[test]
+    fn it_takes_a_bundle_after_overlays() {
+        let options = parse(&["--overlay", "ha.yaml", "bundle.yaml"]);
+        assert_eq!(options.overlays, vec![PathBuf::from("ha.yaml")]);
+        assert_eq!(options.bundle_path, Some(PathBuf::from("bundle.yaml")));
+    }
+}
 
 #[derive(Debug, StructOpt)]
 #[structopt(
     name = "bundle-lint",
-    about = "A program to lint Juju models and bundles."
+    about = "A program to lint Juju models and bundles.",
+    raw(setting = "structopt::clap::AppSettings::SubcommandsNegateReqs")
 )]
 struct Opt {
     /// Activate debug mode
@@ -31,13 +68,189 @@ struct Opt {
         default_value = "gh:ChrisMacNaughton/bundlelint-rules"
     )]
     config_repo: String,
-    /// Bundle to lint
-    #[structopt(name = "bundle")]
-    bundle_path: PathBuf,
+    /// A directory holding charm schemas, used to validate application options
+    /// and relations.
+    ///
+    /// This can be a single charm directory or a cache with one directory
+    /// per charm, each containing the charm's config.yaml and metadata.yaml.
+    /// May be given more than once.
+    #[structopt(
+        name = "charm_schemas",
+        long = "charm-schemas",
+        parse(from_os_str),
+        raw(number_of_values = "1")
+    )]
+    charm_schemas: Vec<PathBuf>,
+    /// An overlay to merge into the bundle before linting, as with
+    /// `juju deploy --overlay`. May be given more than once.
+    #[structopt(
+        name = "overlay",
+        long = "overlay",
+        parse(from_os_str),
+        raw(number_of_values = "1")
+    )]
+    overlays: Vec<PathBuf>,
+    /// Print findings as `text` or as a `json` report.
+    #[structopt(
+        name = "format",
+        long = "format",
+        default_value = "text",
+        raw(global = "true")
+    )]
+    format: Format,
+    /// Lint the bundle of a deployed model, named `model` or
+    /// `controller:model`, instead of a bundle file.
+    #[structopt(name = "model", short = "m", long = "model")]
+    model: Option<String>,
+    /// Lint the bundle of every model on the controller, several at a time,
+    /// and print a summary.
+    #[structopt(name = "all_models", long = "all-models", conflicts_with = "model")]
+    all_models: bool,
+    /// Saved `juju status --format yaml` or `--format json` output to lint
+    /// alongside the bundle, or `-` to read it from stdin.
+    #[structopt(
+        name = "status",
+        long = "status",
+        parse(from_os_str),
+        raw(conflicts_with_all = r#"&["juju_status", "all_models"]"#)
+    )]
+    status: Option<PathBuf>,
+    /// The bundle this one replaces, to check the change with the rules'
+    /// change requirements. The overlays aren't merged into it.
+    #[structopt(
+        name = "previous",
+        long = "previous",
+        parse(from_os_str),
+        conflicts_with = "all_models"
+    )]
+    previous: Option<PathBuf>,
+    /// Lint the output of `juju status` for `--model` alongside its bundle.
+    #[structopt(name = "juju_status", long = "juju-status", requires = "model")]
+    juju_status: bool,
+    /// The controller for `--all-models`, instead of the current one.
+    #[structopt(name = "controller", long = "controller", requires = "all_models")]
+    controller: Option<String>,
+    /// The `juju` command to run for `--model`.
+    #[structopt(
+        name = "juju_bin",
+        long = "juju-bin",
+        default_value = "juju",
+        parse(from_os_str),
+        raw(global = "true")
+    )]
+    juju_bin: PathBuf,
+    /// Bundle to lint, or `-` to read it from stdin
+    #[structopt(
+        name = "bundle",
+        parse(from_os_str),
+        raw(required_unless_one = r#"&["model", "all_models"]"#),
+        raw(conflicts_with_all = r#"&["model", "all_models"]"#)
+    )]
+    bundle_path: Option<PathBuf>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Show how a deployed model, or a second bundle, differs from a bundle.
+    #[structopt(name = "diff")]
+    Diff {
+        /// The intended bundle.
+        #[structopt(name = "bundle", parse(from_os_str))]
+        bundle_path: PathBuf,
+        /// The bundle to compare it with.
+        #[structopt(
+            name = "other",
+            parse(from_os_str),
+            required_unless = "model",
+            conflicts_with = "model"
+        )]
+        other_path: Option<PathBuf>,
+        /// Compare with the bundle of a deployed model, named `model` or
+        /// `controller:model`.
+        #[structopt(name = "model", short = "m", long = "model")]
+        model: Option<String>,
+    },
+    /// Rewrite a bundle in canonical form, keeping its comments.
+    #[structopt(name = "fmt")]
+    Fmt {
+        /// The bundle to format in place, or `-` to read it from stdin and
+        /// write it to stdout.
+        #[structopt(name = "bundle", parse(from_os_str))]
+        bundle_path: PathBuf,
+        /// Don't write the bundle, fail if it isn't formatted.
+        #[structopt(long = "check")]
+        check: bool,
+    },
+}
+
+#[derive(Debug)]
+enum Format {
+    Text,
+    Json,
 }
 
-fn main() -> Result<(), Error> {
-    let options = Opt::from_args();
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("{} is not a format, use text or json", s)),
+        }
+    }
+}
+
+/// The process exit code for each way `bundle-lint` can fail:
+///
+/// | Code | Meaning                                              |
+/// |------|------------------------------------------------------|
+/// | 0    | The bundle passed every lint                         |
+/// | 1    | The bundle failed a lint                             |
+/// | 2    | The bundle, an overlay or a status couldn't be read  |
+/// | 3    | The bundle, an overlay or a status isn't valid       |
+/// | 4    | The rule repository couldn't be fetched              |
+/// | 5    | The rule repository refused access                   |
+/// | 6    | The rule repository has no such branch or tag        |
+/// | 7    | The local rule directory doesn't exist               |
+/// | 8    | A rule file isn't valid, or two rules share a name   |
+/// | 9    | Running `juju` failed                                |
+/// | 10   | The rule cache or rule directory couldn't be used    |
+/// | 11   | The arguments are wrong                              |
+/// | 12   | `fmt` can't format the bundle without changing it    |
+///
+/// `diff` fails with a lint failure when the bundles differ, and `fmt --check`
+/// when the bundle isn't formatted.
+fn exit_code(error: &JujuLintError) -> i32 {
+    match error {
+        JujuLintError::LintFailure => 1,
+        JujuLintError::BundleIo { .. } => 2,
+        JujuLintError::BundleParse { .. } => 3,
+        JujuLintError::RuleFetchNetwork { .. } => 4,
+        JujuLintError::RuleFetchAuth { .. } => 5,
+        JujuLintError::RuleRefNotFound { .. } => 6,
+        JujuLintError::RuleDirMissing { .. } => 7,
+        JujuLintError::RuleParse { .. } | JujuLintError::DuplicateCheck { .. } => 8,
+        JujuLintError::JujuCli { .. } => 9,
+        JujuLintError::RuleIo { .. } => 10,
+        JujuLintError::BundleFormat { .. } => 12,
+    }
+}
+
+/// The exit code for arguments that can't be parsed, so that it can't be
+/// mistaken for a failed lint.
+const USAGE_ERROR: i32 = 11;
+
+fn main() {
+    let options = Opt::from_iter_safe(std::env::args()).unwrap_or_else(|e| match e.kind {
+        ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+        _ => {
+            eprintln!("{}", e.message);
+            process::exit(USAGE_ERROR);
+        }
+    });
     let level = if options.debug {
         Level::Trace
     } else {
@@ -45,43 +258,221 @@ fn main() -> Result<(), Error> {
     };
     simple_logger::init_with_level(level).expect("Couldn't initialize logger");
     debug!("Running with {:?}", options);
-    let bundle = match juju::Model::load_bundle(options.bundle_path.clone()) {
-        Ok(b) => b,
-        Err(e) => {
-            println!(
-                "Failed to load the bundle ({}): {}",
-                options.bundle_path.display(),
-                e
-            );
-            process::exit(1);
+    if let Err(e) = run(&options) {
+        if let JujuLintError::LintFailure = e {
+        } else {
+            println!("{}", e);
         }
+        process::exit(exit_code(&e));
+    }
+}
+
+fn linter(options: &Opt) -> Result<Linter, JujuLintError> {
+    let mut builder = Linter::builder().rule_source(&options.config_repo);
+    for path in &options.charm_schemas {
+        builder = builder.charm_schemas(path.clone());
+    }
+    for path in &options.overlays {
+        builder = builder.overlay(path.clone());
+    }
+    builder.build()
+}
+
+fn run(options: &Opt) -> Result<(), JujuLintError> {
+    let juju = juju::Juju::new(options.juju_bin.clone());
+    if let Some(Command::Diff {
+        bundle_path,
+        other_path,
+        model,
+    }) = &options.command
+    {
+        return run_diff(
+            options,
+            &juju,
+            bundle_path,
+            other_path.as_deref(),
+            model.as_deref(),
+        );
+    }
+    if let Some(Command::Fmt { bundle_path, check }) = &options.command {
+        return run_fmt(bundle_path, *check);
+    }
+    if options.all_models {
+        return run_all_models(options, &juju);
+    }
+    let bundle = match (&options.model, &options.bundle_path) {
+        (Some(model), _) => juju::Model::export_bundle(&juju, model)?,
+        (None, Some(path)) => juju::Model::load_bundle(path.clone())?,
+        (None, None) => unreachable!("structopt requires a bundle or a model"),
     };
     debug!("Loaded bundle: {:#?}", bundle);
-    let rules = match bundle_lint::import_rules(&options.config_repo) {
-        Ok(b) => b,
-        Err(e) => {
-            println!(
-                "Failed to load the configuration at {}: {}",
-                options.config_repo, e
-            );
-            process::exit(1);
-        }
+    let status = match (&options.model, &options.status) {
+        (Some(model), _) if options.juju_status => Some(juju::Model::status(&juju, model)?),
+        (_, Some(path)) => Some(juju::Model::load_status(path.clone())?),
+        _ => None,
     };
-    debug!("Loaded rules: {:#?}", rules);
-    let mut passing = true;
-    for rule in rules {
-        match rule.verify(&bundle) {
-            bundle_lint::VerificationResult::Pass => {}
-            bundle_lint::VerificationResult::Fail { reason } => {
-                println!("{} rule failed: {}", rule.charm_name, reason);
-                passing = false;
+    let previous = match &options.previous {
+        Some(path) => Some(juju::Model::load_bundle(path.clone())?),
+        None => None,
+    };
+    let report = linter(options)?.lint_with(&bundle, status.as_ref(), previous.as_ref())?;
+    match options.format {
+        Format::Text => {
+            for finding in &report.findings {
+                println!("{}", finding);
+            }
+            if report.passed() {
+                println!("Passed all configured lints");
             }
         }
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("A report is valid JSON")
+        ),
     }
-    if passing {
-        println!("Passed all configured lints");
+    if report.passed() {
         Ok(())
     } else {
-        Err(bundle_lint::JujuLintError::LintFailure.into())
+        Err(JujuLintError::LintFailure)
+    }
+}
+
+/// Lints every model on the controller. Exits with the code of the first
+/// model that couldn't be linted, if any, and fails if any model failed lint.
+fn run_all_models(options: &Opt, juju: &juju::Juju) -> Result<(), JujuLintError> {
+    let models = juju::Model::list(juju, options.controller.as_deref())?;
+    let linter = linter(options)?;
+    let reports = linter.lint_models(juju, &models);
+    match options.format {
+        Format::Text => {
+            for model in &reports {
+                println!("== {} ==", model.model);
+                match (&model.report, &model.error) {
+                    (Some(report), _) => {
+                        for finding in &report.findings {
+                            println!("{}", finding);
+                        }
+                        if report.passed() {
+                            println!("Passed all configured lints");
+                        }
+                    }
+                    (None, Some(error)) => println!("{}", error),
+                    (None, None) => {}
+                }
+                println!();
+            }
+            print_summary(&reports);
+        }
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&reports).expect("A report is valid JSON")
+        ),
+    }
+    // The errors were reported with their models, so exit without repeating
+    // them.
+    if let Some(error) = reports.iter().find_map(|model| model.error.as_ref()) {
+        process::exit(exit_code(error));
+    }
+    if reports.iter().all(ModelReport::passed) {
+        Ok(())
+    } else {
+        Err(JujuLintError::LintFailure)
+    }
+}
+
+fn print_summary(reports: &[ModelReport]) {
+    let width = reports
+        .iter()
+        .map(|model| model.model.len())
+        .chain(Some("MODEL".len()))
+        .max()
+        .unwrap_or(0);
+    println!(
+        "{:width$}  {:7}  FINDINGS",
+        "MODEL",
+        "RESULT",
+        width = width
+    );
+    for model in reports {
+        let (result, findings) = match &model.report {
+            Some(report) if report.passed() => ("passed", "0".to_string()),
+            Some(report) => ("failed", report.findings.len().to_string()),
+            None => ("error", "-".to_string()),
+        };
+        println!(
+            "{:width$}  {:7}  {}",
+            model.model,
+            result,
+            findings,
+            width = width
+        );
+    }
+}
+
+/// Prints how the other bundle differs from the first, failing if it does.
+fn run_diff(
+    options: &Opt,
+    juju: &juju::Juju,
+    bundle_path: &Path,
+    other_path: Option<&Path>,
+    model: Option<&str>,
+) -> Result<(), JujuLintError> {
+    let bundle = juju::Model::load_bundle(bundle_path.to_path_buf())?;
+    let other = match (model, other_path) {
+        (Some(model), _) => juju::Model::export_bundle(juju, model)?,
+        (None, Some(path)) => juju::Model::load_bundle(path.to_path_buf())?,
+        (None, None) => unreachable!("structopt requires a bundle or a model"),
+    };
+    let diff = bundle.diff(&other);
+    match options.format {
+        Format::Text if diff.is_empty() => println!("No differences"),
+        Format::Text => print!("{}", diff),
+        Format::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&diff).expect("A diff is valid JSON")
+        ),
+    }
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        Err(JujuLintError::LintFailure)
+    }
+}
+
+/// Formats a bundle in place, or with `check`, fails if it isn't formatted.
+fn run_fmt(bundle_path: &Path, check: bool) -> Result<(), JujuLintError> {
+    let display = bundle_path.display().to_string();
+    let io = |e: io::Error| JujuLintError::BundleIo {
+        path: display.clone(),
+        reason: e.to_string(),
+    };
+    let stdin = bundle_path.as_os_str() == "-";
+    let input = if stdin {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input).map_err(io)?;
+        input
+    } else {
+        fs::read_to_string(bundle_path).map_err(io)?
+    };
+    juju::Bundle::load(&input).map_err(|e| JujuLintError::BundleParse {
+        path: display.clone(),
+        reason: e.to_string(),
+    })?;
+    let formatted = juju::format_bundle(&input).map_err(|e| JujuLintError::BundleFormat {
+        path: display.clone(),
+        reason: e.to_string(),
+    })?;
+    if check {
+        if formatted == input {
+            return Ok(());
+        }
+        println!("{} isn't formatted", display);
+        return Err(JujuLintError::LintFailure);
+    }
+    if stdin {
+        print!("{}", formatted);
+    } else if formatted != input {
+        fs::write(bundle_path, formatted).map_err(io)?;
     }
+    Ok(())
 }
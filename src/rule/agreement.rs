@@ -0,0 +1,117 @@
+use crate::juju::Bundle;
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::rule::{display_value, Comparison, VerificationResult};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLE: &str = r#"
+applications:
+  keystone:
+    charm: cs:keystone
+    options:
+      region: RegionOne
+  glance:
+    charm: cs:glance
+    options:
+      region: RegionTwo
+  nova-cloud-controller:
+    charm: cs:nova-cloud-controller
+    options:
+      region: RegionOne
+  ceph-mon:
+    charm: cs:ceph-mon
+"#;
+
+    #[test]
+    fn it_names_the_disagreeing_applications() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let agreement = Agreement::parse("option: region").unwrap();
+        assert_eq!(
+            agreement.verify(&bundle),
+            VerificationResult::Fail {
+                reason: "region differs between applications: glance is \"RegionTwo\"; keystone, nova-cloud-controller are \"RegionOne\"".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_only_compares_the_given_charms() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let agreement = Agreement::parse(
+            r#"---
+option: region
+charms: [keystone, nova-cloud-controller, ceph-mon]"#,
+        )
+        .unwrap();
+        assert_eq!(agreement.verify(&bundle), VerificationResult::Pass);
+    }
+}
+
+/// Every application that sets an option must set it to the same value,
+/// e.g. `region` across the OpenStack API charms.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Agreement {
+    pub option: String,
+    /// Limits the check to applications of these charms; every application
+    /// is checked when empty.
+    #[serde(default)]
+    pub charms: Vec<String>,
+    #[serde(default)]
+    pub comparison: Comparison,
+}
+
+impl Agreement {
+    pub fn parse(input: &str) -> Result<Agreement, Error> {
+        Ok(serde_yaml::from_str(input)?)
+    }
+
+    pub fn verify(&self, bundle: &Bundle) -> VerificationResult {
+        let mut values: Vec<_> = bundle
+            .applications
+            .iter()
+            .filter(|(_, application)| {
+                self.charms.is_empty()
+                    || self
+                        .charms
+                        .iter()
+                        .any(|charm| charm == application.charm_name())
+            })
+            .filter_map(|(name, application)| Some((name, application.option(&self.option)?)))
+            .collect();
+        values.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut groups: Vec<(&Value, Vec<&str>)> = vec![];
+        for (name, value) in values {
+            match groups
+                .iter_mut()
+                .find(|(v, _)| self.comparison.equal(v, value))
+            {
+                Some((_, names)) => names.push(name),
+                None => groups.push((value, vec![name])),
+            }
+        }
+        if groups.len() < 2 {
+            return VerificationResult::Pass;
+        }
+        groups.sort_by(|a, b| a.1.cmp(&b.1));
+        let groups: Vec<_> = groups
+            .iter()
+            .map(|(value, names)| {
+                let verb = if names.len() == 1 { "is" } else { "are" };
+                format!("{} {} {}", names.join(", "), verb, display_value(value))
+            })
+            .collect();
+        VerificationResult::Fail {
+            reason: format!(
+                "{} differs between applications: {}",
+                self.option,
+                groups.join("; ")
+            ),
+        }
+    }
+}
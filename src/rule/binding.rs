@@ -0,0 +1,321 @@
+use crate::charm::Metadata;
+use crate::juju::{Application, Bundle, RelationEndpoint};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::rule::VerificationResult;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLE: &str = r#"
+applications:
+  keystone:
+    charm: cs:keystone
+    bindings:
+      "": oam-space
+      public: public-api
+  ceph-osd:
+    charm: cs:ceph-osd
+    bindings:
+      mon: ceph-access
+  ceph-mon:
+    charm: cs:ceph-mon
+    bindings:
+      "": oam-space
+      osd: ceph-access
+  mysql:
+    charm: cs:percona-cluster
+    bindings:
+      "": db-space
+relations:
+- - ceph-osd:mon
+  - ceph-mon:osd
+- - keystone:shared-db
+  - mysql:shared-db
+- - ceph-osd
+  - ceph-mon
+"#;
+
+    #[test]
+    fn it_parses_a_binding() {
+        let binding_yaml = r#"---
+spaces:
+  public: public-api
+all_bound: true"#;
+        let binding = Binding::parse(binding_yaml).unwrap();
+        assert_eq!(binding.spaces["public"], "public-api");
+        assert!(binding.all_bound);
+    }
+
+    #[test]
+    fn it_requires_an_endpoint_in_a_space() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let keystone = bundle.application("keystone").unwrap();
+        let mut binding = Binding::default();
+        binding
+            .spaces
+            .insert("public".to_string(), "public-api".to_string());
+        assert_eq!(
+            binding.verify("keystone", keystone, &bundle),
+            VerificationResult::Pass
+        );
+        binding
+            .spaces
+            .insert("admin".to_string(), "admin-api".to_string());
+        assert_eq!(
+            binding.verify("keystone", keystone, &bundle),
+            VerificationResult::Fail {
+                reason: "keystone / admin is bound to oam-space, requires admin-api".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_requires_every_endpoint_to_be_bound() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let binding = Binding {
+            all_bound: true,
+            ..Default::default()
+        };
+        let ceph_mon = bundle.application("ceph-mon").unwrap();
+        assert_eq!(
+            binding.verify("ceph-mon", ceph_mon, &bundle),
+            VerificationResult::Pass
+        );
+        let ceph_osd = bundle.application("ceph-osd").unwrap();
+        assert_eq!(
+            binding.verify("ceph-osd", ceph_osd, &bundle),
+            VerificationResult::Fail {
+                reason: "ceph-osd has no space binding for its default endpoints".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_requires_declared_endpoints_to_be_bound() {
+        let bundle = Bundle::load(
+            r#"
+applications:
+  keystone:
+    charm: cs:keystone
+    bindings:
+      identity-service: public-api
+      shared-db: db-space
+"#,
+        )
+        .unwrap();
+        let binding = Binding {
+            all_bound: true,
+            ..Default::default()
+        };
+        let keystone = bundle.application("keystone").unwrap();
+        let metadata =
+            Metadata::parse(include_str!("../../tests/charms/keystone/metadata.yaml")).unwrap();
+        assert_eq!(
+            binding.verify("keystone", keystone, &bundle),
+            VerificationResult::Pass
+        );
+        assert_eq!(
+            binding.verify_with_metadata("keystone", keystone, &bundle, Some(&metadata)),
+            VerificationResult::Fail {
+                reason: "keystone / identity-admin is not bound to a space".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_requires_related_applications_to_share_a_space() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let binding = Binding {
+            shared_with_related: true,
+            ..Default::default()
+        };
+        let ceph_mon = bundle.application("ceph-mon").unwrap();
+        assert_eq!(
+            binding.verify("ceph-mon", ceph_mon, &bundle),
+            VerificationResult::Pass
+        );
+        let keystone = bundle.application("keystone").unwrap();
+        assert_eq!(
+            binding.verify("keystone", keystone, &bundle),
+            VerificationResult::Fail {
+                reason: "keystone:shared-db is bound to oam-space but mysql:shared-db is bound to db-space".into()
+            }
+        );
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Binding {
+    /// Endpoints that must be bound to a specific space.
+    #[serde(default)]
+    pub spaces: BTreeMap<String, String>,
+    /// Every endpoint must be bound to a space: those the charm's metadata
+    /// declares, when charm schemas are given, and those in the bundle's
+    /// relations. Without the metadata, an endpoint with no relation isn't
+    /// checked unless it is listed in `spaces`.
+    #[serde(default)]
+    pub all_bound: bool,
+    /// Both ends of each relation must be bound to the same space.
+    #[serde(default)]
+    pub shared_with_related: bool,
+}
+
+impl Binding {
+    pub fn parse(input: &str) -> Result<Binding, Error> {
+        Ok(serde_yaml::from_str(input)?)
+    }
+
+    pub fn verify(
+        &self,
+        name: &str,
+        application: &Application,
+        bundle: &Bundle,
+    ) -> VerificationResult {
+        self.verify_with_metadata(name, application, bundle, None)
+    }
+
+    /// Verifies the binding, checking every endpoint declared in `metadata`
+    /// for `all_bound` if the charm's metadata is given.
+    pub fn verify_with_metadata(
+        &self,
+        name: &str,
+        application: &Application,
+        bundle: &Bundle,
+        metadata: Option<&Metadata>,
+    ) -> VerificationResult {
+        if let VerificationResult::Fail { reason: f } = self.verify_spaces(name, application) {
+            return VerificationResult::Fail { reason: f };
+        }
+        if self.all_bound {
+            if let VerificationResult::Fail { reason: f } =
+                self.verify_all_bound(name, application, bundle, metadata)
+            {
+                return VerificationResult::Fail { reason: f };
+            }
+        }
+        if self.shared_with_related {
+            if let VerificationResult::Fail { reason: f } = self.verify_shared(name, bundle) {
+                return VerificationResult::Fail { reason: f };
+            }
+        }
+        VerificationResult::Pass
+    }
+
+    fn verify_spaces(&self, name: &str, application: &Application) -> VerificationResult {
+        for (endpoint, space) in &self.spaces {
+            match application.binding(endpoint) {
+                Some(bound) if bound == space => {}
+                Some(bound) => {
+                    return VerificationResult::Fail {
+                        reason: format!(
+                            "{} / {} is bound to {}, requires {}",
+                            name, endpoint, bound, space
+                        ),
+                    }
+                }
+                None => {
+                    return VerificationResult::Fail {
+                        reason: format!(
+                            "{} / {} is not bound to a space, requires {}",
+                            name, endpoint, space
+                        ),
+                    }
+                }
+            }
+        }
+        VerificationResult::Pass
+    }
+
+    fn verify_all_bound(
+        &self,
+        name: &str,
+        application: &Application,
+        bundle: &Bundle,
+        metadata: Option<&Metadata>,
+    ) -> VerificationResult {
+        if application.bindings().is_empty() {
+            return VerificationResult::Fail {
+                reason: format!("{} has no space bindings", name),
+            };
+        }
+        if let Some((endpoint, _)) = application
+            .bindings()
+            .iter()
+            .find(|(_, space)| space.is_empty())
+        {
+            return VerificationResult::Fail {
+                reason: format!("{} / {} is bound to an empty space", name, endpoint),
+            };
+        }
+        let declared = metadata
+            .map(|metadata| metadata.endpoints())
+            .unwrap_or_default();
+        let related = related_endpoints(name, bundle);
+        let endpoints = declared.iter().map(|endpoint| endpoint.name).chain(
+            related
+                .iter()
+                .map(|end| end.endpoint.as_deref().unwrap_or("")),
+        );
+        for endpoint in endpoints {
+            if application.binding(endpoint).is_none() {
+                return VerificationResult::Fail {
+                    reason: if endpoint.is_empty() {
+                        format!("{} has no space binding for its default endpoints", name)
+                    } else {
+                        format!("{} / {} is not bound to a space", name, endpoint)
+                    },
+                };
+            }
+        }
+        VerificationResult::Pass
+    }
+
+    fn verify_shared(&self, name: &str, bundle: &Bundle) -> VerificationResult {
+        for relation in bundle.relation_endpoints() {
+            if !relation.iter().any(|end| end.application == name) {
+                continue;
+            }
+            let spaces: Vec<_> = relation
+                .iter()
+                .map(|end| {
+                    bundle
+                        .application(&end.application)
+                        .and_then(|a| a.binding(end.endpoint.as_deref().unwrap_or("")))
+                })
+                .collect();
+            if let (Some(left), Some(right)) = (spaces[0], spaces[1]) {
+                if left != right {
+                    let (first, second) = if relation[0].application == name {
+                        (0, 1)
+                    } else {
+                        (1, 0)
+                    };
+                    return VerificationResult::Fail {
+                        reason: format!(
+                            "{} is bound to {} but {} is bound to {}",
+                            relation[first],
+                            spaces[first].unwrap_or_default(),
+                            relation[second],
+                            spaces[second].unwrap_or_default()
+                        ),
+                    };
+                }
+            }
+        }
+        VerificationResult::Pass
+    }
+}
+
+/// The ends of the bundle's relations that are on `name`.
+fn related_endpoints(name: &str, bundle: &Bundle) -> Vec<RelationEndpoint> {
+    bundle
+        .relation_endpoints()
+        .into_iter()
+        .flat_map(|relation| relation.to_vec())
+        .filter(|end| end.application == name)
+        .collect()
+}
@@ -0,0 +1,258 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::juju::{pair_matches, Bundle, RelationEndpoint};
+use crate::rule::expression::Expression;
+use crate::rule::{display_value, VerificationResult};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = r#"
+applications:
+  ceph-mon:
+    charm: cs:ceph-mon
+    num_units: 3
+  ceph-osd:
+    charm: cs:ceph-osd
+    num_units: 3
+    options:
+      osd-devices: /dev/sdb
+  keystone:
+    charm: cs:keystone
+  mysql:
+    charm: cs:percona-cluster
+relations:
+  - [keystone:shared-db, mysql:shared-db]
+"#;
+
+    const NEW: &str = r#"
+applications:
+  ceph-mon:
+    charm: cs:ceph-mon
+    num_units: 1
+  ceph-osd:
+    charm: cs:ceph-osd
+    num_units: 3
+    options:
+      osd-devices: /dev/sdc
+  keystone:
+    charm: cs:keystone
+  mysql:
+    charm: cs:percona-cluster
+"#;
+
+    fn verify(application: &str, condition: &str) -> VerificationResult {
+        let condition: ChangeCondition = serde_yaml::from_str(condition).unwrap();
+        condition.verify(
+            application,
+            &Bundle::load(OLD).unwrap(),
+            &Bundle::load(NEW).unwrap(),
+        )
+    }
+
+    #[test]
+    fn it_keeps_quorum() {
+        assert_eq!(
+            verify("ceph-mon", "{num_units: true, require: new > old / 2}"),
+            VerificationResult::Fail {
+                reason: "ceph-mon num_units changes from 3 to 1, requires new > old / 2".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_forbids_changing_an_option_once_set() {
+        assert_eq!(
+            verify(
+                "ceph-osd",
+                "{option: osd-devices, require: old == null or new == old}"
+            ),
+            VerificationResult::Fail {
+                reason: "ceph-osd / osd-devices changes from \"/dev/sdb\" to \"/dev/sdc\", requires old == null or new == old".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_forbids_removing_a_relation() {
+        assert_eq!(
+            verify(
+                "",
+                "{relation: [keystone:shared-db, mysql], require: new}"
+            ),
+            VerificationResult::Fail {
+                reason: "relation keystone:shared-db <-> mysql changes from present to absent, requires new".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_keeps_critical_relations() {
+        let (old, new) = (Bundle::load(OLD).unwrap(), Bundle::load(NEW).unwrap());
+        let relation = |yaml| serde_yaml::from_str::<[RelationEndpoint; 2]>(yaml).unwrap();
+        assert_eq!(
+            verify_kept(&relation("[mysql, keystone]"), &old, &new),
+            VerificationResult::Fail {
+                reason: "critical relation mysql <-> keystone is removed".into()
+            }
+        );
+        assert_eq!(
+            verify_kept(&relation("[mysql, keystone]"), &new, &old),
+            VerificationResult::Pass
+        );
+        assert_eq!(
+            verify_kept(&relation("[ceph-osd:mon, ceph-mon:osd]"), &old, &new),
+            VerificationResult::Pass
+        );
+    }
+
+    #[test]
+    fn it_ignores_what_is_unchanged() {
+        assert_eq!(
+            verify("ceph-osd", "{num_units: true, require: new > old}"),
+            VerificationResult::Pass
+        );
+    }
+
+    #[test]
+    fn it_needs_one_subject() {
+        assert!(serde_yaml::from_str::<ChangeCondition>("require: new").is_err());
+        assert!(serde_yaml::from_str::<ChangeCondition>(
+            "{num_units: true, option: debug, require: new}"
+        )
+        .is_err());
+    }
+}
+
+/// A requirement on a change between two bundles, such as a proposed bundle
+/// and the one that is deployed. `require` is checked against the old and
+/// new values of the subject, only when they differ.
+///
+/// The subject is one of `num_units` or an `option` of the rule's
+/// application, where a missing application has no units and no options,
+/// or a `relation`, whose values are whether it is present.
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "ChangeSpec")]
+pub struct ChangeCondition {
+    pub subject: Subject,
+    pub require: Expression,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Subject {
+    NumUnits,
+    Option(String),
+    Relation([RelationEndpoint; 2]),
+}
+
+/// How a change condition is written in a rule file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChangeSpec {
+    #[serde(default)]
+    num_units: bool,
+    #[serde(default)]
+    option: Option<String>,
+    #[serde(default)]
+    relation: Option<[RelationEndpoint; 2]>,
+    require: Expression,
+}
+
+impl std::convert::TryFrom<ChangeSpec> for ChangeCondition {
+    type Error = String;
+
+    fn try_from(spec: ChangeSpec) -> Result<ChangeCondition, String> {
+        let subject = match (spec.num_units, spec.option, spec.relation) {
+            (true, None, None) => Subject::NumUnits,
+            (false, Some(option), None) => Subject::Option(option),
+            (false, None, Some(relation)) => Subject::Relation(relation),
+            _ => return Err("A change needs one of num_units, option or relation".to_string()),
+        };
+        Ok(ChangeCondition {
+            subject,
+            require: spec.require,
+        })
+    }
+}
+
+impl Subject {
+    fn value(&self, application: &str, bundle: &Bundle) -> Value {
+        match self {
+            Subject::NumUnits => bundle
+                .application(application)
+                .map(|a| a.num_units())
+                .unwrap_or(0)
+                .into(),
+            Subject::Option(option) => bundle
+                .application(application)
+                .and_then(|a| a.option(option))
+                .cloned()
+                .unwrap_or(Value::Null),
+            Subject::Relation(relation) => bundle
+                .relation_endpoints()
+                .iter()
+                .any(|r| pair_matches(relation, r))
+                .into(),
+        }
+    }
+
+    fn describe(&self, application: &str) -> String {
+        match self {
+            Subject::NumUnits => format!("{} num_units", application),
+            Subject::Option(option) => format!("{} / {}", application, option),
+            Subject::Relation([a, b]) => format!("relation {} <-> {}", a, b),
+        }
+    }
+}
+
+/// Checks that a relation tagged critical that is in `old` is still in
+/// `new`.
+pub fn verify_kept(
+    relation: &[RelationEndpoint; 2],
+    old: &Bundle,
+    new: &Bundle,
+) -> VerificationResult {
+    let subject = Subject::Relation(relation.clone());
+    if subject.value("", old) == Value::Bool(true) && subject.value("", new) == Value::Bool(false) {
+        let [a, b] = relation;
+        return VerificationResult::Fail {
+            reason: format!("critical relation {} <-> {} is removed", a, b),
+        };
+    }
+    VerificationResult::Pass
+}
+
+impl ChangeCondition {
+    pub fn verify(&self, application: &str, old: &Bundle, new: &Bundle) -> VerificationResult {
+        let old_value = self.subject.value(application, old);
+        let new_value = self.subject.value(application, new);
+        if old_value == new_value {
+            return VerificationResult::Pass;
+        }
+        let display = |value: &Value| match (&self.subject, value) {
+            (Subject::Relation(_), Value::Bool(true)) => "present".to_string(),
+            (Subject::Relation(_), _) => "absent".to_string(),
+            (_, Value::Null) => "unset".to_string(),
+            (_, value) => display_value(value),
+        };
+        let subject = self.subject.describe(application);
+        match self.require.evaluate(&old_value, &new_value) {
+            Ok(true) => VerificationResult::Pass,
+            Ok(false) => VerificationResult::Fail {
+                reason: format!(
+                    "{} changes from {} to {}, requires {}",
+                    subject,
+                    display(&old_value),
+                    display(&new_value),
+                    self.require
+                ),
+            },
+            Err(e) => VerificationResult::Fail {
+                reason: format!("Failed to evaluate {} for {}: {}", self.require, subject, e),
+            },
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(yaml: &str) -> Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn it_compares_numbers_by_value_when_strict() {
+        assert!(Comparison::Strict.equal(&value("1"), &value("1.0")));
+        assert!(!Comparison::Strict.equal(&value("1"), &value("'1'")));
+        assert!(!Comparison::Strict.equal(&value("true"), &value("'True'")));
+        assert!(Comparison::Strict.equal(&value("'True'"), &value("'True'")));
+    }
+
+    #[test]
+    fn it_compares_string_forms_when_loose() {
+        assert!(Comparison::Loose.equal(&value("true"), &value("'True'")));
+        assert!(Comparison::Loose.equal(&value("false"), &value("'no'")));
+        assert!(Comparison::Loose.equal(&value("1"), &value("'1.0'")));
+        assert!(Comparison::Loose.equal(&value("0.25"), &value("'0.25'")));
+        assert!(Comparison::Loose.equal(&value("'br-ex'"), &value("'br-ex'")));
+        assert!(!Comparison::Loose.equal(&value("true"), &value("1")));
+        assert!(!Comparison::Loose.equal(&value("'br-ex'"), &value("'BR-EX'")));
+    }
+
+    #[test]
+    fn it_parses_a_comparison() {
+        let comparison: Comparison = serde_yaml::from_str("loose").unwrap();
+        assert_eq!(comparison, Comparison::Loose);
+    }
+}
+
+/// How option values are compared against the values in a rule.
+///
+/// `strict` requires the same YAML type, although numbers are compared by
+/// value so that `1` equals `1.0`. `loose` also treats booleans and numbers
+/// as equal to their string forms, so `true` equals `'True'` and `1` equals
+/// `'1.0'`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Comparison {
+    #[default]
+    Strict,
+    Loose,
+}
+
+#[derive(Debug, PartialEq)]
+enum Scalar<'a> {
+    Bool(bool),
+    Number(f64),
+    Other(&'a Value),
+}
+
+impl Comparison {
+    pub fn equal(self, a: &Value, b: &Value) -> bool {
+        self.scalar(a) == self.scalar(b)
+    }
+
+    fn scalar(self, value: &Value) -> Scalar<'_> {
+        match value {
+            Value::Bool(b) => Scalar::Bool(*b),
+            Value::Number(n) => n
+                .as_f64()
+                .map(Scalar::Number)
+                .unwrap_or(Scalar::Other(value)),
+            Value::String(s) if self == Comparison::Loose => {
+                match s.to_lowercase().as_str() {
+                    "true" | "yes" | "on" => return Scalar::Bool(true),
+                    "false" | "no" | "off" => return Scalar::Bool(false),
+                    _ => {}
+                }
+                s.trim()
+                    .parse()
+                    .map(Scalar::Number)
+                    .unwrap_or(Scalar::Other(value))
+            }
+            _ => Scalar::Other(value),
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use crate::juju::{pair_matches, Bundle, RelationEndpoint};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::rule::{option_matches, Comparison, Expected, Predicate};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLE: &str = r#"
+applications:
+  keystone:
+    charm: cs:keystone
+    options:
+      vip: 10.0.0.10
+  ceph-radosgw:
+    charm: cs:ceph-radosgw
+  neutron-api:
+    charm: cs:neutron-api
+    options:
+      enable-dvr: true
+relations:
+- - ceph-radosgw:identity-service
+  - keystone:identity-service
+"#;
+
+    #[test]
+    fn it_parses_a_condition() {
+        let condition = Condition::parse(
+            r#"---
+all_of:
+  - application: ceph-radosgw
+  - application: keystone
+not:
+  config:
+    application: neutron-api
+    name: enable-dvr
+    value: true"#,
+        )
+        .unwrap();
+        assert_eq!(condition.all_of.len(), 2);
+        assert!(condition.not.is_some());
+    }
+
+    #[test]
+    fn it_evaluates_combinators() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let condition = Condition::parse(
+            r#"---
+all_of:
+  - application: ceph-radosgw
+  - application: keystone
+any_of:
+  - application: glance
+  - relation: ['keystone', 'ceph-radosgw']"#,
+        )
+        .unwrap();
+        assert!(condition.evaluate(&bundle));
+        let condition = Condition::parse(
+            r#"---
+not:
+  config:
+    application: neutron-api
+    name: enable-dvr
+    value: true"#,
+        )
+        .unwrap();
+        assert!(!condition.evaluate(&bundle));
+    }
+
+    #[test]
+    fn it_treats_an_empty_condition_as_true() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        assert!(Condition::default().evaluate(&bundle));
+    }
+
+    #[test]
+    fn it_checks_application_presence_by_charm() {
+        let bundle = Bundle::load(
+            r#"
+applications:
+  mysql:
+    charm: cs:percona-cluster-281
+  keystone-mysql-router:
+    charm: cs:mysql-router
+  glance-mysql-router:
+    charm: cs:mysql-router
+"#,
+        )
+        .unwrap();
+        let absent = Condition::parse(
+            r#"---
+application:
+  charm: percona-cluster
+  max: 0"#,
+        )
+        .unwrap();
+        assert_eq!(
+            absent.failure(&bundle).unwrap(),
+            "applications using charm percona-cluster are absent (found 1)"
+        );
+        let present = Condition::parse("application: {charm: mysql-innodb-cluster}").unwrap();
+        assert_eq!(
+            present.failure(&bundle).unwrap(),
+            "an application using charm mysql-innodb-cluster is present (found 0)"
+        );
+        let routers = Condition::parse(
+            r#"---
+application:
+  charm: mysql-router
+  min: 2
+  max: 3"#,
+        )
+        .unwrap();
+        assert!(routers.evaluate(&bundle));
+        let named =
+            Condition::parse("application: {name: mysql, charm: mysql-innodb-cluster}").unwrap();
+        assert!(!named.evaluate(&bundle));
+    }
+
+    #[test]
+    fn it_rejects_an_unknown_or_empty_application() {
+        assert!(Condition::parse("application: {chram: keystone}").is_err());
+        assert!(Condition::parse("application: {min: 1}").is_err());
+        assert!(Condition::parse("application: {}").is_err());
+    }
+
+    #[test]
+    fn it_describes_the_failing_part() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let condition = Condition::parse(
+            r#"---
+all_of:
+  - relation: ['keystone', 'ceph-radosgw']
+  - config:
+      application: keystone
+      name: vip
+      value:
+        matches: '^192\.168\.'"#,
+        )
+        .unwrap();
+        assert_eq!(
+            condition.failure(&bundle).unwrap(),
+            "keystone / vip is matching \"^192\\\\.168\\\\.\""
+        );
+    }
+}
+
+/// A condition on a config option of an application.
+#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ConfigCondition {
+    pub application: String,
+    pub name: String,
+    /// The expected value; when omitted the option only needs to be set.
+    #[serde(default)]
+    pub value: Option<Expected>,
+    #[serde(default)]
+    pub comparison: Comparison,
+}
+
+impl ConfigCondition {
+    fn expected(&self) -> Expected {
+        match &self.value {
+            Some(value) => value.clone(),
+            None => Expected::Predicate(Predicate {
+                is_set: true,
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn evaluate(&self, bundle: &Bundle) -> bool {
+        match bundle.application(&self.application) {
+            Some(application) => {
+                option_matches(application, &self.name, &self.expected(), self.comparison)
+            }
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for ConfigCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} / {} is {}",
+            self.application,
+            self.name,
+            self.expected()
+        )
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ApplicationSpec {
+    Name(String),
+    Full(ApplicationFields),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ApplicationFields {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    charm: Option<String>,
+    #[serde(default)]
+    min: Option<usize>,
+    #[serde(default)]
+    max: Option<usize>,
+}
+
+/// A condition on the applications in a bundle, selected by name, charm or
+/// both.
+///
+/// By default at least one application must match. `min` and `max` bound
+/// the number that match instead, so `max: 0` asserts that none do.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "ApplicationSpec")]
+pub struct ApplicationCondition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The charm name, without schema, series or revision.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub charm: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<usize>,
+}
+
+impl std::convert::TryFrom<ApplicationSpec> for ApplicationCondition {
+    type Error = String;
+
+    fn try_from(spec: ApplicationSpec) -> Result<ApplicationCondition, String> {
+        match spec {
+            ApplicationSpec::Name(name) => Ok(ApplicationCondition {
+                name: Some(name),
+                ..Default::default()
+            }),
+            ApplicationSpec::Full(ApplicationFields {
+                name: None,
+                charm: None,
+                ..
+            }) => Err("An application condition needs a name or a charm".to_string()),
+            ApplicationSpec::Full(ApplicationFields {
+                name,
+                charm,
+                min,
+                max,
+            }) => Ok(ApplicationCondition {
+                name,
+                charm,
+                min,
+                max,
+            }),
+        }
+    }
+}
+
+impl ApplicationCondition {
+    /// The number of applications in `bundle` that match.
+    pub fn count(&self, bundle: &Bundle) -> usize {
+        bundle
+            .applications
+            .iter()
+            .filter(|(name, application)| {
+                self.name.as_ref().map(|n| n == *name).unwrap_or(true)
+                    && self
+                        .charm
+                        .as_ref()
+                        .map(|c| c == application.charm_name())
+                        .unwrap_or(true)
+            })
+            .count()
+    }
+
+    fn min(&self) -> usize {
+        match (self.min, self.max) {
+            (Some(min), _) => min,
+            (None, Some(_)) => 0,
+            (None, None) => 1,
+        }
+    }
+
+    fn allows(&self, count: usize) -> bool {
+        count >= self.min() && self.max.map(|max| count <= max).unwrap_or(true)
+    }
+}
+
+impl fmt::Display for ApplicationCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let subject = match (&self.name, &self.charm) {
+            (Some(name), Some(charm)) => format!("application {} using charm {}", name, charm),
+            (Some(name), None) => format!("application {}", name),
+            (None, Some(charm)) => format!("applications using charm {}", charm),
+            (None, None) => "applications".to_string(),
+        };
+        let verb = if self.name.is_some() { "is" } else { "are" };
+        match (self.min(), self.max) {
+            (1, None) => match (&self.name, &self.charm) {
+                (None, Some(charm)) => write!(f, "an application using charm {} is present", charm),
+                (None, None) => write!(f, "an application is present"),
+                _ => write!(f, "{} is present", subject),
+            },
+            (_, Some(0)) => write!(f, "{} {} absent", subject, verb),
+            (min, None) => write!(f, "at least {} {}", min, subject),
+            (0, Some(max)) => write!(f, "at most {} {}", max, subject),
+            (min, Some(max)) => write!(f, "between {} and {} {}", min, max, subject),
+        }
+    }
+}
+
+/// A condition over a bundle, used in a rule's `when` and `then`.
+///
+/// Every part that is given must hold, so a mapping with several keys is an
+/// implicit `all_of`. An empty condition always holds.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub all_of: Vec<Condition>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub any_of: Vec<Condition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not: Option<Box<Condition>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<ConfigCondition>,
+    /// A relation between two applications is present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relation: Option<[RelationEndpoint; 2]>,
+    /// Applications are present, or absent. A plain string checks for an
+    /// application with that name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub application: Option<ApplicationCondition>,
+}
+
+impl Condition {
+    pub fn parse(input: &str) -> Result<Condition, Error> {
+        Ok(serde_yaml::from_str(input)?)
+    }
+
+    pub fn evaluate(&self, bundle: &Bundle) -> bool {
+        self.failure(bundle).is_none()
+    }
+
+    /// Describes the first part of the condition that doesn't hold, or
+    /// `None` if it holds.
+    pub fn failure(&self, bundle: &Bundle) -> Option<String> {
+        for condition in &self.all_of {
+            if let Some(failure) = condition.failure(bundle) {
+                return Some(failure);
+            }
+        }
+        if !self.any_of.is_empty() && !self.any_of.iter().any(|c| c.evaluate(bundle)) {
+            return Some(format!("any of ({})", join(&self.any_of)));
+        }
+        if let Some(not) = &self.not {
+            if not.evaluate(bundle) {
+                return Some(format!("not ({})", not));
+            }
+        }
+        if let Some(config) = &self.config {
+            if !config.evaluate(bundle) {
+                return Some(config.to_string());
+            }
+        }
+        if let Some(relation) = &self.relation {
+            if !bundle
+                .relation_endpoints()
+                .iter()
+                .any(|b_relation| pair_matches(relation, b_relation))
+            {
+                return Some(format!("relation {} <-> {}", relation[0], relation[1]));
+            }
+        }
+        if let Some(application) = &self.application {
+            let count = application.count(bundle);
+            if !application.allows(count) {
+                return Some(format!("{} (found {})", application, count));
+            }
+        }
+        None
+    }
+}
+
+fn join(conditions: &[Condition]) -> String {
+    let parts: Vec<_> = conditions.iter().map(|c| c.to_string()).collect();
+    parts.join(", ")
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = vec![];
+        if !self.all_of.is_empty() {
+            parts.push(format!("all of ({})", join(&self.all_of)));
+        }
+        if !self.any_of.is_empty() {
+            parts.push(format!("any of ({})", join(&self.any_of)));
+        }
+        if let Some(not) = &self.not {
+            parts.push(format!("not ({})", not));
+        }
+        if let Some(config) = &self.config {
+            parts.push(config.to_string());
+        }
+        if let Some(relation) = &self.relation {
+            parts.push(format!("relation {} <-> {}", relation[0], relation[1]));
+        }
+        if let Some(application) = &self.application {
+            parts.push(application.to_string());
+        }
+        if parts.is_empty() {
+            write!(f, "always")
+        } else {
+            write!(f, "{}", parts.join(" and "))
+        }
+    }
+}
@@ -1,10 +1,15 @@
 use crate::juju::Application;
 use crate::juju::Bundle;
 use failure::Error;
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
 use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 
-use crate::rule::VerificationResult;
+use crate::rule::{display_value, option_matches, Comparison, Expected, VerificationResult};
 
 #[cfg(test)]
 mod tests {
@@ -17,15 +22,191 @@ config_value: 'True'
 requires:
   neutron-openvswitch:
     - name: bridge-mappings"#;
-        let config = Config::parse(&config_yaml).unwrap();
+        let config = Config::parse(config_yaml).unwrap();
         assert_eq!(config.config_name, "enable-dvr");
+        assert_eq!(config.comparison, Comparison::Strict);
+    }
+
+    const BUNDLE: &str = r#"
+applications:
+  neutron-api:
+    charm: cs:neutron-api
+    options:
+      enable-dvr: true
+      worker-multiplier: 1
+  neutron-openvswitch:
+    charm: cs:neutron-openvswitch
+    options:
+      enable-local-dhcp-and-metadata: 'True'
+"#;
+
+    #[test]
+    fn it_only_triggers_on_the_same_type_when_strict() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let application = bundle.application("neutron-api").unwrap();
+        let config = Config::parse(
+            r#"config_name: enable-dvr
+config_value: 'True'
+requires:
+  neutron-openvswitch:
+    - name: bridge-mappings"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.verify(application, &bundle),
+            VerificationResult::Pass
+        );
+    }
+
+    #[test]
+    fn it_compares_loosely_when_asked() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let application = bundle.application("neutron-api").unwrap();
+        let config = Config::parse(
+            r#"config_name: enable-dvr
+config_value: 'True'
+comparison: loose
+requires:
+  neutron-openvswitch:
+    - name: enable-local-dhcp-and-metadata
+      value: true
+  neutron-api:
+    - name: worker-multiplier
+      value: '1.0'"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.verify(application, &bundle),
+            VerificationResult::Pass
+        );
+    }
+
+    #[test]
+    fn it_triggers_and_requires_on_predicates() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let application = bundle.application("neutron-api").unwrap();
+        let config = Config::parse(
+            r#"config_name: worker-multiplier
+config_value:
+  min: 0.5
+requires:
+  neutron-openvswitch:
+    - name: bridge-mappings
+      value:
+        contains: physnet1:br-ex"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.verify(application, &bundle),
+            VerificationResult::Fail {
+                reason: "neutron-openvswitch / bridge-mappings has a missing config value".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_forbids_on_predicates() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let application = bundle.application("neutron-api").unwrap();
+        let config = Config::parse(
+            r#"config_name: enable-dvr
+config_value: true
+forbids:
+  neutron-openvswitch:
+    - name: bridge-mappings
+      value:
+        is_unset: true"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.verify(application, &bundle),
+            VerificationResult::Fail {
+                reason: "neutron-openvswitch / bridge-mappings is unset, forbids unset".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_requires_the_same_value_as_another_application() {
+        let bundle = Bundle::load(
+            r#"
+applications:
+  nova-cloud-controller:
+    charm: cs:nova-cloud-controller
+    options:
+      console-access-protocol: spice
+  nova-compute:
+    charm: cs:nova-compute
+    options:
+      console-access-protocol: novnc
+"#,
+        )
+        .unwrap();
+        let application = bundle.application("nova-cloud-controller").unwrap();
+        let config = Config::parse(
+            r#"config_name: console-access-protocol
+config_value:
+  is_set: true
+requires:
+  nova-compute:
+    - name: console-access-protocol
+      same_as: nova-cloud-controller:console-access-protocol"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.verify(application, &bundle),
+            VerificationResult::Fail {
+                reason: "nova-compute / console-access-protocol is \"novnc\", requires the same value as nova-cloud-controller:console-access-protocol (\"spice\")".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_rejects_a_value_with_same_as() {
+        let error = serde_yaml::from_str::<ConfigValue>(
+            "{name: console-access-protocol, value: spice, same_as: nova-cloud-controller:console-access-protocol}",
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("console-access-protocol can't have both a value and same_as"));
+    }
+
+    #[test]
+    fn it_rejects_a_reference_without_an_option() {
+        assert!(serde_yaml::from_str::<OptionReference>("keystone").is_err());
+    }
+
+    #[test]
+    fn it_lets_an_entry_override_the_comparison() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let application = bundle.application("neutron-api").unwrap();
+        let config = Config::parse(
+            r#"config_name: enable-dvr
+config_value: 'True'
+comparison: loose
+requires:
+  neutron-openvswitch:
+    - name: enable-local-dhcp-and-metadata
+      value: true
+      comparison: strict"#,
+        )
+        .unwrap();
+        assert_ne!(
+            config.verify(application, &bundle),
+            VerificationResult::Pass
+        );
     }
 }
 
 #[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Config {
     pub config_name: String,
-    pub config_value: String,
+    pub config_value: Expected,
+    /// How option values are compared, for both the trigger and the
+    /// `requires` / `forbids` entries.
+    #[serde(default)]
+    pub comparison: Comparison,
     #[serde(default)]
     pub requires: HashMap<String, Vec<ConfigValue>>,
     #[serde(default)]
@@ -34,17 +215,20 @@ pub struct Config {
 
 impl Config {
     pub fn parse(input: &str) -> Result<Config, Error> {
-        Ok(serde_yaml::from_str(&input)?)
+        Ok(serde_yaml::from_str(input)?)
     }
     pub fn verify(&self, application: &Application, bundle: &Bundle) -> VerificationResult {
-        if let Some(value) = application.option(&self.config_name) {
-            if *value == self.config_value {
-                if let VerificationResult::Fail { reason: f } = self.verify_required(&bundle) {
-                    return VerificationResult::Fail { reason: f };
-                }
-                if let VerificationResult::Fail { reason: f } = self.verify_forbids(&bundle) {
-                    return VerificationResult::Fail { reason: f };
-                }
+        if option_matches(
+            application,
+            &self.config_name,
+            &self.config_value,
+            self.comparison,
+        ) {
+            if let VerificationResult::Fail { reason: f } = self.verify_required(bundle) {
+                return VerificationResult::Fail { reason: f };
+            }
+            if let VerificationResult::Fail { reason: f } = self.verify_forbids(bundle) {
+                return VerificationResult::Fail { reason: f };
             }
         }
         VerificationResult::Pass
@@ -54,24 +238,50 @@ impl Config {
         for (application, config) in &self.requires {
             if let Some(other_app) = bundle.application(application) {
                 for config in config {
-                    if let Some(value) = other_app.option(&config.name) {
-                        if let Some(ref v) = config.value {
-                            if v != value {
-                                return VerificationResult::Fail {
-                                    reason: format!(
-                                        "{} / {} has an invalid config value ({:?}), requires {:?}",
-                                        application, config.name, v, value
-                                    ),
-                                };
-                            }
+                    if let Some(reference) = &config.same_as {
+                        let comparison = self.comparison_for(config);
+                        let value = other_app.option(&config.name);
+                        let expected = reference.value(bundle);
+                        if !same(value, expected, comparison) {
+                            return VerificationResult::Fail {
+                                reason: format!(
+                                    "{} / {} is {}, requires the same value as {} ({})",
+                                    application,
+                                    config.name,
+                                    display_option(value),
+                                    reference,
+                                    display_option(expected)
+                                ),
+                            };
+                        }
+                        continue;
+                    }
+                    let value = other_app.option(&config.name);
+                    match (&config.value, value) {
+                        (Some(v), _)
+                            if option_matches(
+                                other_app,
+                                &config.name,
+                                v,
+                                self.comparison_for(config),
+                            ) => {}
+                        (None, Some(_)) => {}
+                        (Some(v), Some(value)) => {
+                            return VerificationResult::Fail {
+                                reason: format!(
+                                    "{} / {} has an invalid config value ({:?}), requires {:?}",
+                                    application, config.name, value, v
+                                ),
+                            };
+                        }
+                        (_, None) => {
+                            return VerificationResult::Fail {
+                                reason: format!(
+                                    "{} / {} has a missing config value",
+                                    application, config.name
+                                ),
+                            };
                         }
-                    } else {
-                        return VerificationResult::Fail {
-                            reason: format!(
-                                "{} / {} has a missing config value",
-                                application, config.name
-                            ),
-                        };
                     }
                 }
             }
@@ -83,37 +293,173 @@ impl Config {
         for (application, config) in &self.forbids {
             if let Some(other_app) = bundle.application(application) {
                 for config in config {
-                    if let Some(value) = other_app.option(&config.name) {
-                        match config.value {
-                            Some(ref v) => {
-                                if v == value {
-                                    return VerificationResult::Fail {
-                                        reason: format!(
-                                            "{} / {} has an invalid config value ({:?}), forbids {:?}",
-                                            application, config.name, v, value
-                                        ),
-                                    };
-                                }
-                            }
-                            None => {
-                                return VerificationResult::Fail {
-                                    reason: format!(
-                                        "{} / {} has an extra config value, forbids {:?}",
-                                        application, config.name, value
+                    if let Some(reference) = &config.same_as {
+                        let value = other_app.option(&config.name);
+                        let expected = reference.value(bundle);
+                        if same(value, expected, self.comparison_for(config)) {
+                            return VerificationResult::Fail {
+                                reason: format!(
+                                    "{} / {} is {}, forbids the same value as {}",
+                                    application,
+                                    config.name,
+                                    display_option(value),
+                                    reference
+                                ),
+                            };
+                        }
+                        continue;
+                    }
+                    let value = other_app.option(&config.name);
+                    match (&config.value, value) {
+                        (Some(v), value)
+                            if option_matches(
+                                other_app,
+                                &config.name,
+                                v,
+                                self.comparison_for(config),
+                            ) =>
+                        {
+                            return VerificationResult::Fail {
+                                reason: match value {
+                                    Some(value) => format!(
+                                        "{} / {} has an invalid config value ({:?}), forbids {:?}",
+                                        application, config.name, value, v
                                     ),
-                                }
+                                    None => format!(
+                                        "{} / {} is unset, forbids {}",
+                                        application, config.name, v
+                                    ),
+                                },
+                            };
+                        }
+                        (None, Some(value)) => {
+                            return VerificationResult::Fail {
+                                reason: format!(
+                                    "{} / {} has an extra config value, forbids {:?}",
+                                    application, config.name, value
+                                ),
                             }
                         }
+                        _ => {}
                     }
                 }
             }
         }
         VerificationResult::Pass
     }
+
+    fn comparison_for(&self, config: &ConfigValue) -> Comparison {
+        config.comparison.unwrap_or(self.comparison)
+    }
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+/// An option of a required or forbidden application, checked against a
+/// `value` or, with `same_as`, against another application's option, but not
+/// both.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "ConfigValueSpec")]
 pub struct ConfigValue {
     pub name: String,
-    pub value: Option<serde_yaml::Value>,
+    pub value: Option<Expected>,
+    /// Compares against another application's option instead of `value`.
+    #[serde(default)]
+    pub same_as: Option<OptionReference>,
+    /// Overrides the comparison of the enclosing `Config`.
+    #[serde(default)]
+    pub comparison: Option<Comparison>,
+}
+
+/// How a config value is written in a rule file.
+#[derive(Deserialize)]
+struct ConfigValueSpec {
+    name: String,
+    #[serde(default)]
+    value: Option<Expected>,
+    #[serde(default)]
+    same_as: Option<OptionReference>,
+    #[serde(default)]
+    comparison: Option<Comparison>,
+}
+
+impl std::convert::TryFrom<ConfigValueSpec> for ConfigValue {
+    type Error = String;
+
+    fn try_from(spec: ConfigValueSpec) -> Result<ConfigValue, String> {
+        if spec.value.is_some() && spec.same_as.is_some() {
+            return Err(format!("{} can't have both a value and same_as", spec.name));
+        }
+        Ok(ConfigValue {
+            name: spec.name,
+            value: spec.value,
+            same_as: spec.same_as,
+            comparison: spec.comparison,
+        })
+    }
+}
+
+/// Whether two options are equal, treating two unset options as equal.
+fn same(a: Option<&Value>, b: Option<&Value>, comparison: Comparison) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => comparison.equal(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn display_option(value: Option<&Value>) -> String {
+    value
+        .map(display_value)
+        .unwrap_or_else(|| "unset".to_string())
+}
+
+/// An option of an application, written as `application:option`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OptionReference {
+    pub application: String,
+    pub option: String,
+}
+
+impl OptionReference {
+    /// The referenced option's value, or `None` if it or its application
+    /// is missing.
+    pub fn value<'a>(&self, bundle: &'a Bundle) -> Option<&'a Value> {
+        bundle.application(&self.application)?.option(&self.option)
+    }
+}
+
+impl FromStr for OptionReference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OptionReference, String> {
+        let mut parts = s.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some(application), Some(option)) if !application.is_empty() && !option.is_empty() => {
+                Ok(OptionReference {
+                    application: application.to_string(),
+                    option: option.to_string(),
+                })
+            }
+            _ => Err(format!("{:?} is not of the form application:option", s)),
+        }
+    }
+}
+
+impl fmt::Display for OptionReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.application, self.option)
+    }
+}
+
+impl Serialize for OptionReference {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for OptionReference {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<OptionReference, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
 }
@@ -0,0 +1,408 @@
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::fmt;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(expression: &str, old: Value, new: Value) -> Result<bool, String> {
+        expression
+            .parse::<Expression>()
+            .unwrap()
+            .evaluate(&old, &new)
+    }
+
+    #[test]
+    fn it_does_arithmetic() {
+        assert_eq!(evaluate("new > old / 2", 3.into(), 2.into()), Ok(true));
+        assert_eq!(evaluate("new > old / 2", 3.into(), 1.into()), Ok(false));
+        assert_eq!(
+            evaluate("new >= old - 1 and new % 2 == 1", 4.into(), 3.into()),
+            Ok(true)
+        );
+        assert_eq!(evaluate("-(new * 2) < -old", 1.into(), 1.into()), Ok(true));
+    }
+
+    #[test]
+    fn it_compares_strings_and_unset_values() {
+        let devices = Value::from("/dev/sdb");
+        assert_eq!(
+            evaluate("old == null or new == old", Value::Null, devices.clone()),
+            Ok(true)
+        );
+        assert_eq!(
+            evaluate("old == null or new == old", devices, "/dev/sdc".into()),
+            Ok(false)
+        );
+        assert_eq!(
+            evaluate("new != 'ovs'", "ovn".into(), "ovs".into()),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn it_uses_booleans() {
+        assert_eq!(
+            evaluate("new or not old", true.into(), false.into()),
+            Ok(false)
+        );
+        assert_eq!(
+            evaluate("new or not old", false.into(), false.into()),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn it_rejects_bad_expressions() {
+        assert!("new >".parse::<Expression>().is_err());
+        assert!("new == 'open".parse::<Expression>().is_err());
+        assert!("older > 1".parse::<Expression>().is_err());
+        assert!(serde_yaml::from_str::<Expression>("new ) old").is_err());
+        assert_eq!(
+            evaluate("new + 1", "a".into(), "b".into()),
+            Err("\"b\" is not a number".to_string())
+        );
+        assert_eq!(
+            evaluate("new + 1", 1.into(), 1.into()),
+            Err("2 is not true or false".to_string())
+        );
+    }
+}
+
+/// A condition on the old and new values of something a change touches,
+/// e.g. `new > old / 2` or `old == null or new == old`.
+///
+/// It supports numbers, quoted strings, `true`, `false` and `null`, which
+/// an unset value is; `+`, `-`, `*`, `/` and `%`; comparisons with `==`,
+/// `!=`, `<`, `<=`, `>` and `>=`; `and`, `or` and `not`; and parentheses.
+#[derive(Clone, Debug)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Literal(Value),
+    Old,
+    New,
+    Not(Box<Node>),
+    Negate(Box<Node>),
+    Binary(Box<Node>, Operator, Box<Node>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    String(String),
+    Word(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &[
+    "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "(", ")",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap_or_default();
+        if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if c == '\'' || c == '"' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| format!("{} is missing its closing quote", rest))?;
+            tokens.push(Token::String(rest[1..=end].to_string()));
+            rest = &rest[end + 2..];
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = rest[..end]
+                .parse()
+                .map_err(|_| format!("{} is not a number", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            return Err(format!("Unexpected {:?}", c));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser, from the loosest binding operator to the
+/// tightest.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn word(&mut self, word: &str) -> bool {
+        if self.peek() == Some(&Token::Word(word.to_string())) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn symbol(&mut self, symbols: &[(&'static str, Operator)]) -> Option<Operator> {
+        let (_, operator) = symbols
+            .iter()
+            .find(|(symbol, _)| self.peek() == Some(&Token::Symbol(symbol)))?;
+        self.position += 1;
+        Some(*operator)
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut node = self.and()?;
+        while self.word("or") {
+            node = Node::Binary(Box::new(node), Operator::Or, Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut node = self.not()?;
+        while self.word("and") {
+            node = Node::Binary(Box::new(node), Operator::And, Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, String> {
+        if self.word("not") {
+            return Ok(Node::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        let node = self.sum()?;
+        let operators = [
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<=", Operator::LessOrEqual),
+            (">=", Operator::GreaterOrEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ];
+        match self.symbol(&operators) {
+            Some(operator) => Ok(Node::Binary(
+                Box::new(node),
+                operator,
+                Box::new(self.sum()?),
+            )),
+            None => Ok(node),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut node = self.product()?;
+        let operators = [("+", Operator::Add), ("-", Operator::Subtract)];
+        while let Some(operator) = self.symbol(&operators) {
+            node = Node::Binary(Box::new(node), operator, Box::new(self.product()?));
+        }
+        Ok(node)
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        let operators = [
+            ("*", Operator::Multiply),
+            ("/", Operator::Divide),
+            ("%", Operator::Remainder),
+        ];
+        while let Some(operator) = self.symbol(&operators) {
+            node = Node::Binary(Box::new(node), operator, Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.symbol(&[("-", Operator::Subtract)]).is_some() {
+            return Ok(Node::Negate(Box::new(self.unary()?)));
+        }
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Node::Literal(n.into())),
+            Some(Token::String(s)) => Ok(Node::Literal(s.into())),
+            Some(Token::Word(word)) => match word.as_str() {
+                "old" => Ok(Node::Old),
+                "new" => Ok(Node::New),
+                "true" => Ok(Node::Literal(true.into())),
+                "false" => Ok(Node::Literal(false.into())),
+                "null" => Ok(Node::Literal(Value::Null)),
+                _ => Err(format!("Unknown name {}, use old or new", word)),
+            },
+            Some(Token::Symbol("(")) => {
+                let node = self.or()?;
+                match self.next() {
+                    Some(Token::Symbol(")")) => Ok(node),
+                    _ => Err("Missing )".to_string()),
+                }
+            }
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    value
+        .as_f64()
+        .ok_or_else(|| format!("{} is not a number", super::display_value(value)))
+}
+
+fn boolean(value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("{} is not true or false", super::display_value(value)))
+}
+
+/// Compares numbers by value, so that `3` equals `3.0`, and anything else
+/// as YAML values.
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+impl Node {
+    fn evaluate(&self, old: &Value, new: &Value) -> Result<Value, String> {
+        match self {
+            Node::Literal(value) => Ok(value.clone()),
+            Node::Old => Ok(old.clone()),
+            Node::New => Ok(new.clone()),
+            Node::Not(node) => Ok((!boolean(&node.evaluate(old, new)?)?).into()),
+            Node::Negate(node) => Ok((-number(&node.evaluate(old, new)?)?).into()),
+            Node::Binary(left, Operator::Or, right) => Ok((boolean(&left.evaluate(old, new)?)?
+                || boolean(&right.evaluate(old, new)?)?)
+            .into()),
+            Node::Binary(left, Operator::And, right) => Ok((boolean(&left.evaluate(old, new)?)?
+                && boolean(&right.evaluate(old, new)?)?)
+            .into()),
+            Node::Binary(left, operator, right) => {
+                let left = left.evaluate(old, new)?;
+                let right = right.evaluate(old, new)?;
+                match operator {
+                    Operator::Equal => return Ok(equal(&left, &right).into()),
+                    Operator::NotEqual => return Ok((!equal(&left, &right)).into()),
+                    _ => {}
+                }
+                let (left, right) = (number(&left)?, number(&right)?);
+                Ok(match operator {
+                    Operator::Less => (left < right).into(),
+                    Operator::LessOrEqual => (left <= right).into(),
+                    Operator::Greater => (left > right).into(),
+                    Operator::GreaterOrEqual => (left >= right).into(),
+                    Operator::Add => (left + right).into(),
+                    Operator::Subtract => (left - right).into(),
+                    Operator::Multiply => (left * right).into(),
+                    Operator::Divide => (left / right).into(),
+                    Operator::Remainder => (left % right).into(),
+                    Operator::Or | Operator::And | Operator::Equal | Operator::NotEqual => {
+                        unreachable!("Handled above")
+                    }
+                })
+            }
+        }
+    }
+}
+
+impl Expression {
+    /// Whether the expression holds for `old` and `new`, where an unset
+    /// value is null. Fails if it doesn't evaluate to true or false, or
+    /// does arithmetic on something other than numbers.
+    pub fn evaluate(&self, old: &Value, new: &Value) -> Result<bool, String> {
+        boolean(&self.root.evaluate(old, new)?)
+    }
+}
+
+impl std::str::FromStr for Expression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Expression, String> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+        };
+        let root = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected {:?} in {}", token, s));
+        }
+        Ok(Expression {
+            source: s.to_string(),
+            root,
+        })
+    }
+}
+
+impl PartialEq for Expression {
+    fn eq(&self, other: &Expression) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Expression {}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Serialize for Expression {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Expression {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Expression, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
@@ -1,15 +1,39 @@
-use failure::Error;
 use log::debug;
 use serde::{Deserialize, Serialize};
 
+use crate::charm::SchemaStore;
 use crate::fetch;
-use crate::juju::Bundle;
+use crate::juju::{Bundle, RelationEndpoint, Status};
+use crate::lint::Severity;
+use crate::JujuLintError;
 
+mod agreement;
+mod binding;
+mod change;
+mod compare;
+mod condition;
 mod config;
+mod expression;
+mod predicate;
 mod relation;
+mod script;
+mod status;
+mod subordinate;
+mod units;
 
-pub use config::{Config, ConfigValue};
+pub use agreement::Agreement;
+pub use binding::Binding;
+pub use change::{ChangeCondition, Subject};
+pub use compare::Comparison;
+pub use condition::{ApplicationCondition, Condition, ConfigCondition};
+pub use config::{Config, ConfigValue, OptionReference};
+pub use expression::Expression;
+pub(crate) use predicate::option_matches;
+pub use predicate::{Expected, Pattern, Predicate};
 pub use relation::Relation;
+pub use status::StatusCondition;
+pub use subordinate::Subordinate;
+pub use units::Units;
 
 #[cfg(test)]
 mod tests {
@@ -17,7 +41,7 @@ mod tests {
 
     use super::*;
 
-    const BUNDLE: &'static str = r#"
+    const BUNDLE: &str = r#"
 applications:
   test-thing:
     charm: cs:test-thing
@@ -48,6 +72,49 @@ applications:
         assert_eq!(rules[0].charm_name, "neutron-api");
     }
 
+    #[test]
+    fn it_validates_a_when_then_rule() {
+        let rgw_bundle = r#"
+applications:
+  keystone:
+    charm: cs:keystone
+  ceph-radosgw:
+    charm: cs:ceph-radosgw
+"#;
+        let yaml_s = r#"- name: radosgw-identity
+  when:
+    all_of:
+      - application: ceph-radosgw
+      - application: keystone
+  then:
+    relation: ['ceph-radosgw', 'keystone']
+"#;
+        let rules: Vec<Rule> = serde_yaml::from_str(yaml_s).unwrap();
+        assert_eq!(rules[0].display_name(), "radosgw-identity");
+        assert_eq!(
+            rules[0].verify(&Bundle::load(rgw_bundle).unwrap()),
+            VerificationResult::Fail {
+                reason: "Expected relation ceph-radosgw <-> keystone, when all of (application ceph-radosgw is present, application keystone is present)".into()
+            }
+        );
+        assert_eq!(
+            rules[0].verify(&Bundle::load(BUNDLE).unwrap()),
+            VerificationResult::Pass
+        );
+    }
+
+    #[test]
+    fn it_needs_then_with_when() {
+        let yaml_s = r#"- name: radosgw-identity
+  when:
+    application: ceph-radosgw
+"#;
+        let error = serde_yaml::from_str::<Vec<Rule>>(yaml_s).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("radosgw-identity has `when` without `then`"));
+    }
+
     #[test]
     fn it_validates_a_basic_forbids_rule() {
         let bundle = Bundle::load(BUNDLE).unwrap();
@@ -58,6 +125,7 @@ applications:
                 vec![ConfigValue {
                     name: "conflicts-with-cool-thing".to_string(),
                     value: None,
+                    ..Default::default()
                 }],
             );
             h
@@ -66,11 +134,13 @@ applications:
             charm_name: "test-thing".to_string(),
             config: vec![Config {
                 config_name: "use-cool-thing".to_string(),
-                config_value: "True".to_string(),
+                config_value: "True".into(),
                 requires: HashMap::new(),
                 forbids,
+                ..Default::default()
             }],
             relations: vec![Relation::default()],
+            ..Default::default()
         };
         let verification = rule.verify(&bundle);
 
@@ -87,6 +157,7 @@ applications:
                 vec![ConfigValue {
                     name: "conflicts-with-cool-thing".to_string(),
                     value: Some("True".into()),
+                    ..Default::default()
                 }],
             );
             h
@@ -95,11 +166,13 @@ applications:
             charm_name: "test-thing".to_string(),
             config: vec![Config {
                 config_name: "use-cool-thing".to_string(),
-                config_value: "True".to_string(),
+                config_value: "True".into(),
                 requires: HashMap::new(),
                 forbids,
+                ..Default::default()
             }],
             relations: vec![Relation::default()],
+            ..Default::default()
         };
         let verification = rule.verify(&bundle);
 
@@ -116,6 +189,7 @@ applications:
                 vec![ConfigValue {
                     name: "conflicts-with-cool-thing".to_string(),
                     value: Some("False".into()),
+                    ..Default::default()
                 }],
             );
             h
@@ -124,11 +198,13 @@ applications:
             charm_name: "test-thing".to_string(),
             config: vec![Config {
                 config_name: "use-cool-thing".to_string(),
-                config_value: "True".to_string(),
+                config_value: "True".into(),
                 requires: HashMap::new(),
                 forbids,
+                ..Default::default()
             }],
             relations: vec![Relation::default()],
+            ..Default::default()
         };
         let verification = rule.verify(&bundle);
 
@@ -145,6 +221,7 @@ applications:
                 vec![ConfigValue {
                     name: "required-by-cool-thing".to_string(),
                     value: None,
+                    ..Default::default()
                 }],
             );
             h
@@ -153,30 +230,155 @@ applications:
             charm_name: "test-thing".to_string(),
             config: vec![Config {
                 config_name: "use-cool-thing".to_string(),
-                config_value: "True".to_string(),
+                config_value: "True".into(),
                 requires,
                 forbids: HashMap::new(),
+                ..Default::default()
             }],
             relations: vec![Relation::default()],
+            ..Default::default()
         };
         let verification = rule.verify(&bundle);
         assert_eq!(verification, VerificationResult::Pass);
     }
+
+    #[test]
+    fn it_validates_a_bindings_rule() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let rule = Rule {
+            charm_name: "test-thing".to_string(),
+            bindings: vec![Binding {
+                all_bound: true,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            rule.verify(&bundle),
+            VerificationResult::Fail {
+                reason: "test-thing has no space bindings".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_forbids_removing_a_critical_relation() {
+        let rules: Vec<Rule> = serde_yaml::from_str(
+            r#"- name: critical
+  critical_relations:
+    - [keystone:shared-db, mysql:shared-db]"#,
+        )
+        .unwrap();
+        let previous = Bundle::load(
+            "applications: {keystone: {charm: cs:keystone}, mysql: {charm: cs:mysql}}\nrelations: [[keystone, mysql]]",
+        )
+        .unwrap();
+        let bundle = Bundle::load(
+            "applications: {keystone: {charm: cs:keystone}, mysql: {charm: cs:mysql}}",
+        )
+        .unwrap();
+        assert_eq!(
+            rules[0].verify_change(&previous, &bundle),
+            VerificationResult::Fail {
+                reason: "critical relation keystone:shared-db <-> mysql:shared-db is removed"
+                    .into()
+            }
+        );
+        assert_eq!(
+            rules[0].verify_change(&bundle, &previous),
+            VerificationResult::Pass
+        );
+    }
 }
 
-pub fn import(config_path: &str) -> Result<Vec<Rule>, Error> {
+pub fn import(config_path: &str) -> Result<Vec<Rule>, JujuLintError> {
     let new_config_path = config_path.replace("gh:", "https://github.com/");
     debug!("Loading config from {}", new_config_path);
-    fetch::import(&fetch::load(&new_config_path)?)
+    let mut rules = fetch::import(&fetch::load(&new_config_path)?)?;
+    // Names unnamed rules by the source too, so that the same file name in
+    // two sources doesn't give two rules the same id.
+    for rule in &mut rules {
+        if let Some(source) = &mut rule.source {
+            *source = format!("{}/{}", config_path.trim_end_matches('/'), source);
+        }
+    }
+    Ok(rules)
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Serialize)]
+/// A rule read from a rule file. A rule with `when` must have `then`.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+// Derives `Rule::deserialize` and `Rule::serialize` as inherent functions,
+// which the trait impls below wrap to validate the rule.
+#[serde(remote = "Self")]
 pub struct Rule {
+    /// A name for the rule, used when reporting failures.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The application that `config`, `relations`, `bindings`, `units` and
+    /// `status` apply to.
+    #[serde(default)]
     pub charm_name: String,
+    /// The severity of the rule's findings.
+    #[serde(default)]
+    pub severity: Severity,
     #[serde(default)]
     pub config: Vec<Config>,
     #[serde(default)]
     pub relations: Vec<Relation>,
+    #[serde(default)]
+    pub bindings: Vec<Binding>,
+    #[serde(default)]
+    pub units: Vec<Units>,
+    /// Requirements on the deployed units, checked when `juju status` is
+    /// given.
+    #[serde(default)]
+    pub status: Vec<StatusCondition>,
+    /// Requirements on how a bundle may change from the previous one,
+    /// checked when a previous bundle is given.
+    #[serde(default)]
+    pub changes: Vec<ChangeCondition>,
+    /// Relations tagged critical, which a change may not remove, checked
+    /// when a previous bundle is given.
+    #[serde(default)]
+    pub critical_relations: Vec<[RelationEndpoint; 2]>,
+    /// Pairings of subordinate charms with their principals, checked
+    /// across the whole bundle.
+    #[serde(default)]
+    pub subordinates: Vec<Subordinate>,
+    /// Options that must have the same value wherever they are set.
+    #[serde(default)]
+    pub agreements: Vec<Agreement>,
+    /// A Rhai script run against the bundle, with the `scripting` feature.
+    #[serde(default)]
+    pub script: Option<String>,
+    /// When this condition holds, `then` must hold too.
+    #[serde(default)]
+    pub when: Option<Condition>,
+    #[serde(default)]
+    pub then: Option<Condition>,
+    /// Where the rule was read from, e.g. `gh:org/rules/keystone.yaml#2`,
+    /// which identifies the rule when it has no name.
+    #[serde(skip)]
+    pub source: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for Rule {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Rule, D::Error> {
+        let rule = Rule::deserialize(deserializer)?;
+        if rule.when.is_some() && rule.then.is_none() {
+            return Err(serde::de::Error::custom(format!(
+                "{} has `when` without `then`",
+                rule.display_name()
+            )));
+        }
+        Ok(rule)
+    }
+}
+
+impl Serialize for Rule {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Rule::serialize(self, serializer)
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -185,22 +387,154 @@ pub enum VerificationResult {
     Fail { reason: String },
 }
 
+/// Formats a YAML scalar the way it would be written in a bundle.
+pub(crate) fn display_value(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::Null => "null".to_string(),
+        serde_yaml::Value::Bool(b) => b.to_string(),
+        serde_yaml::Value::Number(n) => n.to_string(),
+        serde_yaml::Value::String(s) => format!("{:?}", s),
+        other => format!("{:?}", other),
+    }
+}
+
 impl Rule {
+    /// The rule's name, or where it was read from when it has none.
+    pub fn id(&self) -> &str {
+        match (&self.name, &self.source) {
+            (Some(name), _) => name,
+            (None, Some(source)) => source,
+            (None, None) => self.display_name(),
+        }
+    }
+
+    pub fn display_name(&self) -> &str {
+        match &self.name {
+            Some(name) => name,
+            None if self.charm_name.is_empty() => "unnamed",
+            None => &self.charm_name,
+        }
+    }
+
     pub fn verify(&self, bundle: &Bundle) -> VerificationResult {
+        self.verify_with_schemas(bundle, None)
+    }
+
+    /// Verifies the rule, using the charm's metadata from `schemas` for
+    /// binding rules when it is there.
+    pub fn verify_with_schemas(
+        &self,
+        bundle: &Bundle,
+        schemas: Option<&SchemaStore>,
+    ) -> VerificationResult {
         if let Some(application) = bundle.application(&self.charm_name) {
             for config in &self.config {
-                if let VerificationResult::Fail { reason: f } = config.verify(&application, &bundle)
-                {
+                if let VerificationResult::Fail { reason: f } = config.verify(application, bundle) {
                     return VerificationResult::Fail { reason: f };
                 }
             }
             for relation in &self.relations {
                 if let VerificationResult::Fail { reason: f } =
-                    relation.verify(&application, &bundle)
+                    relation.verify(&self.charm_name, application, bundle)
                 {
                     return VerificationResult::Fail { reason: f };
                 }
             }
+            let metadata = match schemas {
+                Some(schemas) if !self.bindings.is_empty() => {
+                    match schemas.metadata(application.charm_name()) {
+                        Ok(metadata) => metadata,
+                        Err(e) => {
+                            return VerificationResult::Fail {
+                                reason: format!("{}: {}", self.charm_name, e),
+                            }
+                        }
+                    }
+                }
+                _ => None,
+            };
+            for binding in &self.bindings {
+                if let VerificationResult::Fail { reason: f } = binding.verify_with_metadata(
+                    &self.charm_name,
+                    application,
+                    bundle,
+                    metadata.as_ref(),
+                ) {
+                    return VerificationResult::Fail { reason: f };
+                }
+            }
+            for units in &self.units {
+                if let VerificationResult::Fail { reason: f } =
+                    units.verify(&self.charm_name, application, bundle)
+                {
+                    return VerificationResult::Fail { reason: f };
+                }
+            }
+        }
+        for agreement in &self.agreements {
+            if let VerificationResult::Fail { reason: f } = agreement.verify(bundle) {
+                return VerificationResult::Fail { reason: f };
+            }
+        }
+        if let Some(script) = &self.script {
+            if let VerificationResult::Fail { reason: f } = script::verify(script, bundle) {
+                return VerificationResult::Fail { reason: f };
+            }
+        }
+        for subordinate in &self.subordinates {
+            if let VerificationResult::Fail { reason: f } = subordinate.verify(bundle) {
+                return VerificationResult::Fail { reason: f };
+            }
+        }
+        if let Some(then) = &self.then {
+            if let Some(when) = &self.when {
+                if !when.evaluate(bundle) {
+                    return VerificationResult::Pass;
+                }
+            }
+            if let Some(failure) = then.failure(bundle) {
+                return VerificationResult::Fail {
+                    reason: match &self.when {
+                        Some(when) => format!("Expected {}, when {}", failure, when),
+                        None => format!("Expected {}", failure),
+                    },
+                };
+            }
+        }
+        VerificationResult::Pass
+    }
+
+    /// Checks the rule's `status` requirements against `juju status`. An
+    /// application that isn't deployed passes.
+    pub fn verify_status(&self, status: &Status) -> VerificationResult {
+        if let Some(application) = status.applications.get(&self.charm_name) {
+            for condition in &self.status {
+                if let VerificationResult::Fail { reason: f } =
+                    condition.verify(application, status)
+                {
+                    return VerificationResult::Fail { reason: f };
+                }
+            }
+        }
+        VerificationResult::Pass
+    }
+
+    /// Checks the rule's `changes` requirements on how `bundle` changes
+    /// from `previous`.
+    pub fn verify_change(&self, previous: &Bundle, bundle: &Bundle) -> VerificationResult {
+        for change in &self.changes {
+            if let VerificationResult::Fail { reason: f } =
+                change.verify(&self.charm_name, previous, bundle)
+            {
+                return VerificationResult::Fail { reason: f };
+            }
+        }
+        for relation in &self.critical_relations {
+            if let VerificationResult::Fail { reason: f } =
+                change::verify_kept(relation, previous, bundle)
+            {
+                return VerificationResult::Fail { reason: f };
+            }
         }
         VerificationResult::Pass
     }
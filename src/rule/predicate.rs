@@ -0,0 +1,426 @@
+use regex::Regex;
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::fmt;
+
+use crate::juju::Application;
+use crate::rule::{display_value, Comparison};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected(yaml: &str) -> Expected {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn it_parses_a_literal() {
+        assert_eq!(expected("'True'"), Expected::Literal("True".into()));
+        assert!(expected("true").matches(Some(&true.into()), Comparison::Strict));
+    }
+
+    #[test]
+    fn it_matches_a_regex() {
+        let e = expected("matches: '^br-ex:'");
+        assert!(e.matches(Some(&"br-ex:eth1".into()), Comparison::Strict));
+        assert!(!e.matches(Some(&"br-data:eth1".into()), Comparison::Strict));
+        assert!(!e.matches(None, Comparison::Strict));
+    }
+
+    #[test]
+    fn it_rejects_an_invalid_regex() {
+        assert!(serde_yaml::from_str::<Expected>("matches: '(br-ex'").is_err());
+    }
+
+    #[test]
+    fn it_matches_sets() {
+        let e = expected("one_of: [ovs, ovn]");
+        assert!(e.matches(Some(&"ovn".into()), Comparison::Strict));
+        assert!(!e.matches(Some(&"linuxbridge".into()), Comparison::Strict));
+        let e = expected("not_one_of: [ovs, ovn]");
+        assert!(e.matches(Some(&"linuxbridge".into()), Comparison::Strict));
+        assert!(!e.matches(Some(&"ovs".into()), Comparison::Strict));
+    }
+
+    #[test]
+    fn it_matches_ranges() {
+        let e = expected("{min: 0.1, max: 1}");
+        assert!(e.matches(Some(&Value::from(0.25)), Comparison::Strict));
+        assert!(!e.matches(Some(&Value::from(2)), Comparison::Strict));
+        assert!(!e.matches(Some(&"0.25".into()), Comparison::Strict));
+        assert!(e.matches(Some(&"0.25".into()), Comparison::Loose));
+    }
+
+    #[test]
+    fn it_matches_list_items() {
+        let e = expected("contains: physnet1:br-ex");
+        assert!(e.matches(
+            Some(&"physnet2:br-data physnet1:br-ex".into()),
+            Comparison::Strict
+        ));
+        assert!(!e.matches(Some(&"physnet1:br-ex1".into()), Comparison::Strict));
+    }
+
+    #[test]
+    fn it_matches_presence() {
+        let set = expected("is_set: true");
+        let unset = expected("is_unset: true");
+        let non_empty = expected("non_empty: true");
+        assert!(set.matches(Some(&"".into()), Comparison::Strict));
+        assert!(!set.matches(None, Comparison::Strict));
+        assert!(unset.matches(None, Comparison::Strict));
+        assert!(!unset.matches(Some(&"".into()), Comparison::Strict));
+        assert!(non_empty.matches(Some(&"x".into()), Comparison::Strict));
+        assert!(!non_empty.matches(Some(&"".into()), Comparison::Strict));
+        assert!(!non_empty.matches(Some(&Value::Null), Comparison::Strict));
+    }
+
+    #[test]
+    fn it_rejects_a_nan_bound() {
+        assert!(serde_yaml::from_str::<Expected>("min: .nan").is_err());
+        assert!(serde_yaml::from_str::<Expected>("max: .nan").is_err());
+    }
+
+    #[test]
+    fn it_forbids_included_content() {
+        let mut application = Application::parse(
+            r#"charm: cs:keystone
+options:
+  ssl_cert: include-file://certs/ca.pem"#,
+        )
+        .unwrap();
+        let base = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/includes");
+        application
+            .resolve_includes(&crate::juju::IncludeResolver::new(base))
+            .unwrap();
+        let matches = |yaml: &str| {
+            option_matches(
+                &application,
+                "ssl_cert",
+                &expected(yaml),
+                Comparison::Strict,
+            )
+        };
+        let content = "\"-----BEGIN CERTIFICATE-----\\ntest\\n-----END CERTIFICATE-----\\n\"";
+        assert!(!matches(&format!("not_one_of: [{}]", content)));
+        assert!(!matches("not_one_of: [include-file://certs/ca.pem]"));
+        assert!(matches("not_one_of: [other]"));
+        assert!(matches(&format!("one_of: [{}]", content)));
+        assert!(matches(
+            "{matches: '^include-file://', not_one_of: [other]}"
+        ));
+        assert!(!matches("is_unset: true"));
+    }
+
+    #[test]
+    fn it_describes_a_predicate() {
+        assert_eq!(
+            expected("{one_of: [a, b], non_empty: true}").to_string(),
+            "one of [\"a\", \"b\"] and non-empty"
+        );
+    }
+}
+
+/// Whether an option on `application` is as expected, trying every value
+/// the option can be matched on: an included option matches if its content
+/// or its directive does, but conditions that forbid values, `not_one_of`
+/// and `is_unset`, must hold for both. An unset option is matched as `None`.
+pub(crate) fn option_matches(
+    application: &Application,
+    name: &str,
+    expected: &Expected,
+    comparison: Comparison,
+) -> bool {
+    let values = application.option_values(name);
+    if values.is_empty() {
+        return expected.matches(None, comparison);
+    }
+    match expected {
+        Expected::Predicate(predicate) => {
+            let (requiring, forbidding) = predicate.split();
+            values
+                .iter()
+                .any(|value| requiring.matches(Some(value), comparison))
+                && values
+                    .iter()
+                    .all(|value| forbidding.matches(Some(value), comparison))
+        }
+        Expected::Literal(_) => values
+            .iter()
+            .any(|value| expected.matches(Some(value), comparison)),
+    }
+}
+
+/// What a rule expects an option's value to be: either a literal value or
+/// a predicate. Option values are never mappings, so a mapping is always
+/// read as a predicate.
+#[derive(Clone, Eq, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Expected {
+    Predicate(Predicate),
+    Literal(Value),
+}
+
+impl Expected {
+    /// Whether an option's value, or `None` if it is unset, is as expected.
+    pub fn matches(&self, value: Option<&Value>, comparison: Comparison) -> bool {
+        match self {
+            Expected::Literal(expected) => value
+                .map(|v| comparison.equal(v, expected))
+                .unwrap_or(false),
+            Expected::Predicate(predicate) => predicate.matches(value, comparison),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Expected {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Expected, D::Error> {
+        match Value::deserialize(deserializer)? {
+            value @ Value::Mapping(_) => serde_yaml::from_value(value)
+                .map(Expected::Predicate)
+                .map_err(de::Error::custom),
+            value => Ok(Expected::Literal(value)),
+        }
+    }
+}
+
+impl From<Value> for Expected {
+    fn from(value: Value) -> Expected {
+        Expected::Literal(value)
+    }
+}
+
+impl From<&str> for Expected {
+    fn from(value: &str) -> Expected {
+        Expected::Literal(value.into())
+    }
+}
+
+impl Default for Expected {
+    fn default() -> Expected {
+        Expected::Literal(Value::Null)
+    }
+}
+
+impl fmt::Debug for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expected::Literal(value) => value.fmt(f),
+            Expected::Predicate(predicate) => predicate.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expected::Literal(value) => write!(f, "{}", display_value(value)),
+            Expected::Predicate(predicate) => predicate.fmt(f),
+        }
+    }
+}
+
+/// A regular expression that can be read from and written to a rule file.
+#[derive(Clone, Debug)]
+pub struct Pattern(Regex);
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Pattern, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern).map(Pattern).map_err(de::Error::custom)
+    }
+}
+
+/// Conditions on an option's value. Every condition that is given must
+/// hold.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Predicate {
+    /// The value, as a string, matches a regular expression.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matches: Option<Pattern>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub one_of: Option<Vec<Value>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub not_one_of: Option<Vec<Value>>,
+    #[serde(
+        default,
+        deserialize_with = "bound",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub min: Option<f64>,
+    #[serde(
+        default,
+        deserialize_with = "bound",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max: Option<f64>,
+    /// The value is a space separated list that includes this item.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contains: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub is_set: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub is_unset: bool,
+    /// The value is set to something other than null or an empty string.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub non_empty: bool,
+}
+
+// Bounds are never NaN, as `bound` rejects `.nan`.
+impl Eq for Predicate {}
+
+fn bound<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    match Option::<f64>::deserialize(deserializer)? {
+        Some(bound) if bound.is_nan() => Err(de::Error::custom("a bound can't be NaN")),
+        bound => Ok(bound),
+    }
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+fn as_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn as_number(value: &Value, comparison: Comparison) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) if comparison == Comparison::Loose => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+impl Predicate {
+    /// Splits the predicate into the conditions that require something of
+    /// a value and those that forbid values.
+    fn split(&self) -> (Predicate, Predicate) {
+        let forbidding = Predicate {
+            not_one_of: self.not_one_of.clone(),
+            is_unset: self.is_unset,
+            ..Predicate::default()
+        };
+        let requiring = Predicate {
+            not_one_of: None,
+            is_unset: false,
+            ..self.clone()
+        };
+        (requiring, forbidding)
+    }
+
+    pub fn matches(&self, value: Option<&Value>, comparison: Comparison) -> bool {
+        let value = match value {
+            Some(value) => value,
+            None => return self.is_unset,
+        };
+        if self.is_unset {
+            return false;
+        }
+        if self.non_empty && as_string(value).map(|s| s.is_empty()).unwrap_or(true) {
+            return false;
+        }
+        if let Some(Pattern(regex)) = &self.matches {
+            if !as_string(value)
+                .map(|s| regex.is_match(&s))
+                .unwrap_or(false)
+            {
+                return false;
+            }
+        }
+        if let Some(one_of) = &self.one_of {
+            if !one_of.iter().any(|v| comparison.equal(value, v)) {
+                return false;
+            }
+        }
+        if let Some(not_one_of) = &self.not_one_of {
+            if not_one_of.iter().any(|v| comparison.equal(value, v)) {
+                return false;
+            }
+        }
+        if self.min.is_some() || self.max.is_some() {
+            let number = match as_number(value, comparison) {
+                Some(number) => number,
+                None => return false,
+            };
+            if let Some(min) = self.min {
+                if number < min {
+                    return false;
+                }
+            }
+            if let Some(max) = self.max {
+                if number > max {
+                    return false;
+                }
+            }
+        }
+        if let Some(item) = &self.contains {
+            let list = as_string(value).unwrap_or_default();
+            if !list.split_whitespace().any(|i| i == item) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = vec![];
+        if let Some(Pattern(regex)) = &self.matches {
+            parts.push(format!("matching {:?}", regex.as_str()));
+        }
+        let list = |values: &[Value]| -> String {
+            let values: Vec<_> = values.iter().map(display_value).collect();
+            format!("[{}]", values.join(", "))
+        };
+        if let Some(one_of) = &self.one_of {
+            parts.push(format!("one of {}", list(one_of)));
+        }
+        if let Some(not_one_of) = &self.not_one_of {
+            parts.push(format!("not one of {}", list(not_one_of)));
+        }
+        if let Some(min) = &self.min {
+            parts.push(format!("at least {}", min));
+        }
+        if let Some(max) = &self.max {
+            parts.push(format!("at most {}", max));
+        }
+        if let Some(item) = &self.contains {
+            parts.push(format!("containing {:?}", item));
+        }
+        if self.is_set {
+            parts.push("set".to_string());
+        }
+        if self.is_unset {
+            parts.push("unset".to_string());
+        }
+        if self.non_empty {
+            parts.push("non-empty".to_string());
+        }
+        write!(f, "{}", parts.join(" and "))
+    }
+}
@@ -1,8 +1,8 @@
-use crate::juju::{Application, Bundle};
+use crate::juju::{pair_matches, Application, Bundle, RelationEndpoint};
 use failure::Error;
 use serde::{Deserialize, Serialize};
 
-use crate::rule::VerificationResult;
+use crate::rule::{option_matches, Comparison, Expected, VerificationResult};
 
 #[cfg(test)]
 mod tests {
@@ -17,8 +17,97 @@ config:
 requires:
 - - 'neutron-api:neutron-plugin-api'
   - 'neutron-openvswitch:neutron-plugin-api'"#;
-        let relation = Relation::parse(&rel_yaml).unwrap();
-        assert_eq!(relation.requires[0][0], "neutron-api:neutron-plugin-api");
+        let relation = Relation::parse(rel_yaml).unwrap();
+        assert_eq!(
+            relation.requires[0][0].to_string(),
+            "neutron-api:neutron-plugin-api"
+        );
+    }
+
+    const BUNDLE: &str = r#"
+applications:
+  neutron-api:
+    charm: cs:neutron-api
+  neutron-openvswitch:
+    charm: cs:neutron-openvswitch
+relations:
+- - neutron-openvswitch
+  - neutron-api
+"#;
+
+    #[test]
+    fn it_matches_a_relation_without_endpoints() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let application = bundle.application("neutron-api").unwrap();
+        let relation = Relation::parse(
+            r#"---
+requires:
+- - 'neutron-api:neutron-plugin-api'
+  - 'neutron-openvswitch:neutron-plugin-api'"#,
+        )
+        .unwrap();
+        assert_eq!(
+            relation.verify("neutron-api", application, &bundle),
+            VerificationResult::Pass
+        );
+    }
+
+    #[test]
+    fn it_names_the_missing_relation() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let application = bundle.application("neutron-api").unwrap();
+        let relation = Relation::parse(
+            r#"---
+requires:
+- - 'neutron-api:shared-db'
+  - 'mysql:shared-db'"#,
+        )
+        .unwrap();
+        assert_eq!(
+            relation.verify("neutron-api", application, &bundle),
+            VerificationResult::Fail {
+                reason: "Required relation neutron-api:shared-db <-> mysql:shared-db is missing; neutron-api:shared-db has neutron-openvswitch <-> neutron-api; mysql:shared-db has no relations".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_names_the_trigger_condition() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let application = bundle.application("neutron-api").unwrap();
+        let relation = Relation::parse(
+            r#"---
+config:
+  name: enable-dvr
+  value: true
+forbids:
+- - 'neutron-api:neutron-plugin-api'
+  - 'neutron-openvswitch:neutron-plugin-api'"#,
+        )
+        .unwrap();
+        assert_eq!(
+            relation.verify("neutron-api", application, &bundle),
+            VerificationResult::Fail {
+                reason: "Forbidden relation neutron-api:neutron-plugin-api <-> neutron-openvswitch:neutron-plugin-api is present as neutron-openvswitch <-> neutron-api (when neutron-api / enable-dvr is true)".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_forbids_a_relation_in_either_order() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let application = bundle.application("neutron-api").unwrap();
+        let relation = Relation::parse(
+            r#"---
+forbids:
+- - 'neutron-api'
+  - 'neutron-openvswitch'"#,
+        )
+        .unwrap();
+        assert_ne!(
+            relation.verify("neutron-api", application, &bundle),
+            VerificationResult::Pass
+        );
     }
 }
 
@@ -26,69 +115,122 @@ requires:
 pub struct Relation {
     pub config: Option<ConfigDetail>,
     #[serde(default)]
-    pub requires: Vec<[String; 2]>,
+    pub requires: Vec<[RelationEndpoint; 2]>,
     #[serde(default)]
-    pub forbids: Vec<[String; 2]>,
+    pub forbids: Vec<[RelationEndpoint; 2]>,
 }
 
 #[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct ConfigDetail {
     pub name: String,
-    pub value: serde_yaml::Value,
+    pub value: Expected,
+    #[serde(default)]
+    pub comparison: Comparison,
 }
 
 impl Relation {
     pub fn parse(input: &str) -> Result<Relation, Error> {
-        Ok(serde_yaml::from_str(&input)?)
+        Ok(serde_yaml::from_str(input)?)
     }
-    pub fn verify(&self, application: &Application, bundle: &Bundle) -> VerificationResult {
+    pub fn verify(
+        &self,
+        name: &str,
+        application: &Application,
+        bundle: &Bundle,
+    ) -> VerificationResult {
         if let Some(config) = &self.config {
-            if let Some(value) = application.option(&config.name) {
-                if *value != config.value {
-                    return VerificationResult::Pass;
-                }
+            let applies = match (&config.value, application.option(&config.name)) {
+                // A literal only rules out the relation check when the option
+                // is set to something else.
+                (Expected::Literal(_), None) => true,
+                _ => option_matches(application, &config.name, &config.value, config.comparison),
+            };
+            if !applies {
+                return VerificationResult::Pass;
             }
         }
-        if let VerificationResult::Fail { reason: f } = self.verify_required(&bundle) {
+        if let VerificationResult::Fail { reason: f } = self.verify_required(name, bundle) {
             return VerificationResult::Fail { reason: f };
         }
-        if let VerificationResult::Fail { reason: f } = self.verify_forbids(&bundle) {
+        if let VerificationResult::Fail { reason: f } = self.verify_forbids(name, bundle) {
             return VerificationResult::Fail { reason: f };
         }
         VerificationResult::Pass
     }
 
-    fn verify_required(&self, bundle: &Bundle) -> VerificationResult {
+    fn verify_required(&self, name: &str, bundle: &Bundle) -> VerificationResult {
+        let bundle_relations = bundle.relation_endpoints();
         for relation in &self.requires {
-            let filtered_relations: Vec<_> = bundle
-                .relations
+            if !bundle_relations
                 .iter()
-                .filter(|b_relation| b_relation.iter().all(|k| relation.contains(k)))
-                .collect();
-
-            if filtered_relations.is_empty() {
+                .any(|b_relation| pair_matches(relation, b_relation))
+            {
+                let existing: Vec<_> = relation
+                    .iter()
+                    .map(|end| existing_relations(end, &bundle_relations))
+                    .collect();
                 return VerificationResult::Fail {
-                    reason: format!("Required relation(s) missing: {:?}", filtered_relations),
+                    reason: format!(
+                        "Required relation {} is missing{}; {}",
+                        display_pair(relation),
+                        self.trigger(name),
+                        existing.join("; ")
+                    ),
                 };
             }
         }
         VerificationResult::Pass
     }
 
-    fn verify_forbids(&self, bundle: &Bundle) -> VerificationResult {
+    fn verify_forbids(&self, name: &str, bundle: &Bundle) -> VerificationResult {
+        let bundle_relations = bundle.relation_endpoints();
         for relation in &self.forbids {
-            let filtered_relations: Vec<_> = bundle
-                .relations
+            let filtered_relations: Vec<_> = bundle_relations
                 .iter()
-                .filter(|b_relation| b_relation.iter().all(|k| relation.contains(k)))
+                .filter(|b_relation| pair_matches(relation, b_relation))
+                .map(display_pair)
                 .collect();
 
             if !filtered_relations.is_empty() {
                 return VerificationResult::Fail {
-                    reason: format!("Forbidden relation(s) present: {:?}", filtered_relations),
+                    reason: format!(
+                        "Forbidden relation {} is present as {}{}",
+                        display_pair(relation),
+                        filtered_relations.join(", "),
+                        self.trigger(name),
+                    ),
                 };
             }
         }
         VerificationResult::Pass
     }
+
+    /// Describes the condition under which this relation check applies.
+    fn trigger(&self, name: &str) -> String {
+        match &self.config {
+            Some(config) => format!(" (when {} / {} is {})", name, config.name, config.value),
+            None => String::new(),
+        }
+    }
+}
+
+fn display_pair(relation: &[RelationEndpoint; 2]) -> String {
+    format!("{} <-> {}", relation[0], relation[1])
+}
+
+/// Lists the relations in the bundle that `end` takes part in.
+fn existing_relations(
+    end: &RelationEndpoint,
+    bundle_relations: &[[RelationEndpoint; 2]],
+) -> String {
+    let existing: Vec<_> = bundle_relations
+        .iter()
+        .filter(|relation| relation.iter().any(|b_end| end.matches(b_end)))
+        .map(display_pair)
+        .collect();
+    if existing.is_empty() {
+        format!("{} has no relations", end)
+    } else {
+        format!("{} has {}", end, existing.join(", "))
+    }
 }
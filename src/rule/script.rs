@@ -0,0 +1,267 @@
+use crate::juju::Bundle;
+#[cfg(feature = "scripting")]
+use log::debug;
+
+use crate::rule::VerificationResult;
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use super::*;
+
+    const BUNDLE: &str = r#"
+applications:
+  ceph-osd:
+    charm: cs:ceph-osd
+    num_units: 1
+    options:
+      osd-devices: /dev/sdb
+  ceph-osd-ssd:
+    charm: cs:ceph-osd
+    num_units: 1
+    options:
+      osd-devices: /dev/nvme0n1
+"#;
+
+    #[test]
+    fn it_reports_what_the_script_returns() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let script = r#"
+let devices = 0;
+for application in bundle.applications.values() {
+    if application.charm_name == "ceph-osd" {
+        devices += application.num_units * application.options["osd-devices"].split().len();
+    }
+}
+if devices < 3 {
+    `${devices} OSD devices across ceph-osd, requires at least 3`
+}"#;
+        assert_eq!(
+            verify(script, &bundle),
+            VerificationResult::Fail {
+                reason: "2 OSD devices across ceph-osd, requires at least 3".into()
+            }
+        );
+        assert_eq!(verify("[]", &bundle), VerificationResult::Pass);
+    }
+
+    #[test]
+    fn it_cannot_change_the_bundle() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        assert_eq!(
+            verify("bundle = #{};", &bundle),
+            VerificationResult::Fail {
+                reason: "Script failed: Cannot modify constant bundle (line 1, position 1)".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_cannot_import_modules() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let module = std::env::temp_dir().join(format!("bundle-lint-{}", std::process::id()));
+        std::fs::write(module.with_extension("rhai"), "export const token = 1;").unwrap();
+        let script = format!("import {:?} as m; m::token", module.display().to_string());
+        let result = verify(&script, &bundle);
+        std::fs::remove_file(module.with_extension("rhai")).unwrap();
+        assert_eq!(
+            result,
+            VerificationResult::Fail {
+                reason: format!(
+                    "Script failed: Module not found: {} (line 1, position 8)",
+                    module.display()
+                )
+            }
+        );
+    }
+
+    #[test]
+    fn it_stops_a_runaway_script() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        assert_eq!(
+            verify("loop {}", &bundle),
+            VerificationResult::Fail {
+                reason: "Script failed: Too many operations (line 1, position 6)".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_limits_what_a_script_builds() {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let fails = |script| match verify(script, &bundle) {
+            VerificationResult::Fail { reason } => reason,
+            VerificationResult::Pass => String::new(),
+        };
+        assert_eq!(
+            fails(r#"let s = "x"; loop { s += s; }"#),
+            "Script failed: Length of string too large (line 1, position 23)"
+        );
+        assert_eq!(
+            fails("let a = [0]; loop { a += a; }"),
+            "Script failed: Size of array/BLOB too large (line 1, position 23)"
+        );
+        assert_eq!(
+            fails("fn f(n) { f(n + 1) } f(0)"),
+            "Script failed: Stack overflow (line 1, position 22)"
+        );
+        assert_eq!(
+            fails("let m = #{}; for i in 0..20000 { m[`${i}`] = i; } m.len()"),
+            "Script failed: Size of object map too large (line 1, position 53)"
+        );
+    }
+}
+
+/// The most operations a script may run, so that a looping script can't
+/// hang the lint.
+#[cfg(feature = "scripting")]
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// The longest string a script may build, in bytes, so that it can't
+/// exhaust memory before running out of operations. This leaves room for
+/// options holding included files.
+#[cfg(feature = "scripting")]
+const MAX_STRING_SIZE: usize = 4 * 1024 * 1024;
+
+/// The most items in an array or a map a script may build.
+#[cfg(feature = "scripting")]
+const MAX_COLLECTION_SIZE: usize = 10_000;
+
+/// How deeply a script may call functions, and nest expressions at the top
+/// level and within functions.
+#[cfg(feature = "scripting")]
+const MAX_CALL_LEVELS: usize = 32;
+#[cfg(feature = "scripting")]
+const MAX_EXPR_DEPTHS: (usize, usize) = (64, 32);
+
+/// Runs a rule's Rhai script against `bundle`.
+///
+/// The script sees the bundle as a constant `bundle` map, with
+/// `applications` (each with `charm`, `charm_name`, `num_units`, `to`,
+/// `options` and `bindings`), `machines` and `relations`. It returns a
+/// finding as a string, several as an array of strings, or nothing when
+/// the bundle passes.
+#[cfg(feature = "scripting")]
+pub fn verify(script: &str, bundle: &Bundle) -> VerificationResult {
+    use rhai::module_resolvers::DummyModuleResolver;
+    use rhai::{Dynamic, Engine, Scope};
+
+    let view = match rhai::serde::to_dynamic(view(bundle)) {
+        Ok(view) => view,
+        Err(e) => {
+            return VerificationResult::Fail {
+                reason: format!("Failed to pass the bundle to the script: {}", e),
+            }
+        }
+    };
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_expr_depths(MAX_EXPR_DEPTHS.0, MAX_EXPR_DEPTHS.1);
+    // The default resolver would let `import` read `.rhai` files from disk.
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.on_print(|s| debug!("script: {}", s));
+    engine.on_debug(|s, _, _| debug!("script: {}", s));
+    let mut scope = Scope::new();
+    scope.push_constant_dynamic("bundle", view);
+
+    let result = match engine.eval_with_scope::<Dynamic>(&mut scope, script) {
+        Ok(result) => result,
+        Err(e) => {
+            return VerificationResult::Fail {
+                reason: format!("Script failed: {}", e),
+            }
+        }
+    };
+    let findings: Vec<String> = if result.is_unit() {
+        vec![]
+    } else if result.is_string() {
+        vec![result.to_string()]
+    } else if result.is_array() {
+        result
+            .into_array()
+            .unwrap_or_default()
+            .iter()
+            .map(|finding| finding.to_string())
+            .collect()
+    } else {
+        return VerificationResult::Fail {
+            reason: format!(
+                "Script returned {}, expected a string or an array of strings",
+                result.type_name()
+            ),
+        };
+    };
+    if findings.is_empty() {
+        VerificationResult::Pass
+    } else {
+        VerificationResult::Fail {
+            reason: findings.join("; "),
+        }
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn verify(_script: &str, _bundle: &Bundle) -> VerificationResult {
+    VerificationResult::Fail {
+        reason: "Rule has a script, but bundle-lint was built without the scripting feature".into(),
+    }
+}
+
+/// A copy of the bundle for scripts, with options that were given as
+/// include directives replaced by their content.
+#[cfg(feature = "scripting")]
+fn view(bundle: &Bundle) -> serde_yaml::Value {
+    use serde_yaml::{Mapping, Value};
+
+    let mut applications = Mapping::new();
+    for (name, application) in &bundle.applications {
+        let mut options = Mapping::new();
+        for option in application.options().keys() {
+            if let Some(value) = application.option(option) {
+                options.insert(option.as_str().into(), value.clone());
+            }
+        }
+        let mut bindings = Mapping::new();
+        for (endpoint, space) in application.bindings() {
+            bindings.insert(endpoint.as_str().into(), space.as_str().into());
+        }
+        let mut view = Mapping::new();
+        view.insert("charm".into(), application.charm().into());
+        view.insert("charm_name".into(), application.charm_name().into());
+        view.insert("num_units".into(), (application.num_units() as u64).into());
+        view.insert(
+            "to".into(),
+            Value::Sequence(
+                application
+                    .placements()
+                    .iter()
+                    .map(|p| p.as_str().into())
+                    .collect(),
+            ),
+        );
+        view.insert("options".into(), Value::Mapping(options));
+        view.insert("bindings".into(), Value::Mapping(bindings));
+        applications.insert(name.as_str().into(), Value::Mapping(view));
+    }
+    let mut machines: Vec<_> = bundle.machines.keys().collect();
+    machines.sort();
+    let machines = machines
+        .into_iter()
+        .map(|machine| machine.as_str().into())
+        .collect();
+    let relations = bundle
+        .relations
+        .iter()
+        .map(|[a, b]| Value::Sequence(vec![a.as_str().into(), b.as_str().into()]))
+        .collect();
+
+    let mut view = Mapping::new();
+    view.insert("applications".into(), Value::Mapping(applications));
+    view.insert("machines".into(), Value::Sequence(machines));
+    view.insert("relations".into(), Value::Sequence(relations));
+    Value::Mapping(view)
+}
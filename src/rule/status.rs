@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::juju::{ApplicationStatus, Status, UnitStatus};
+use crate::rule::{display_value, Comparison, Expected, VerificationResult};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = include_str!("../../tests/status/openstack.yaml");
+
+    fn verify(condition: &str) -> VerificationResult {
+        let status = Status::load(STATUS).unwrap();
+        let condition: StatusCondition = serde_yaml::from_str(condition).unwrap();
+        condition.verify(&status.applications["keystone"], &status)
+    }
+
+    #[test]
+    fn it_checks_each_unit() {
+        assert_eq!(
+            verify("workload: active"),
+            VerificationResult::Fail {
+                reason: "keystone/0 workload status is \"error\", requires \"active\"".into()
+            }
+        );
+        assert_eq!(verify("agent: idle"), VerificationResult::Pass);
+    }
+
+    #[test]
+    fn it_checks_agent_versions() {
+        assert_eq!(
+            verify("agent_version: {matches: '^2\\.9\\.4'}"),
+            VerificationResult::Fail {
+                reason:
+                    "keystone/1 agent version is \"2.9.38\", requires matching \"^2\\\\.9\\\\.4\""
+                        .into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_checks_the_series_of_the_machines() {
+        assert_eq!(
+            verify("series: focal"),
+            VerificationResult::Fail {
+                reason: "keystone/1 machine series is \"bionic\", requires \"focal\"".into()
+            }
+        );
+    }
+}
+
+/// Requirements on the units of a deployed application, checked against
+/// `juju status`. Every unit must meet each one that is given.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct StatusCondition {
+    /// The workload status, e.g. `active` or `blocked`.
+    #[serde(default)]
+    pub workload: Option<Expected>,
+    /// The unit agent's status, e.g. `idle` or `executing`.
+    #[serde(default)]
+    pub agent: Option<Expected>,
+    /// The unit agent's version.
+    #[serde(default)]
+    pub agent_version: Option<Expected>,
+    /// The series, or base, of the machine each unit is on.
+    #[serde(default)]
+    pub series: Option<Expected>,
+}
+
+impl StatusCondition {
+    pub fn verify(&self, application: &ApplicationStatus, status: &Status) -> VerificationResult {
+        for (name, unit) in &application.units {
+            let checks = [
+                (
+                    "workload status",
+                    &self.workload,
+                    unit.workload_status.current.clone(),
+                ),
+                (
+                    "agent status",
+                    &self.agent,
+                    unit.juju_status.current.clone(),
+                ),
+                (
+                    "agent version",
+                    &self.agent_version,
+                    unit.juju_status.version.clone(),
+                ),
+                ("machine series", &self.series, machine_series(unit, status)),
+            ];
+            for (field, expected, value) in checks.iter() {
+                let expected = match expected {
+                    Some(expected) => expected,
+                    None => continue,
+                };
+                let value = value.clone().map(Value::String);
+                if !expected.matches(value.as_ref(), Comparison::Loose) {
+                    return VerificationResult::Fail {
+                        reason: format!(
+                            "{} {} is {}, requires {}",
+                            name,
+                            field,
+                            value
+                                .as_ref()
+                                .map(display_value)
+                                .unwrap_or_else(|| "unset".into()),
+                            expected
+                        ),
+                    };
+                }
+            }
+        }
+        VerificationResult::Pass
+    }
+}
+
+fn machine_series(unit: &UnitStatus, status: &Status) -> Option<String> {
+    status.machine(unit.machine.as_ref()?)?.series()
+}
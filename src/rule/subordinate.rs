@@ -0,0 +1,237 @@
+use crate::juju::{Application, Bundle, RelationEndpoint};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use crate::rule::{display_value, Comparison, VerificationResult};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HACLUSTER: &str = r#"---
+charm: hacluster
+endpoint: ha
+principal_options: [vip]
+units_option: cluster_count
+units_default: 3"#;
+
+    fn verify(bundle: &str) -> VerificationResult {
+        let bundle = Bundle::load(bundle).unwrap();
+        Subordinate::parse(HACLUSTER).unwrap().verify(&bundle)
+    }
+
+    #[test]
+    fn it_passes_a_paired_subordinate() {
+        let bundle = r#"
+applications:
+  keystone:
+    charm: cs:keystone
+    num_units: 3
+    options:
+      vip: 10.0.0.10
+  keystone-hacluster:
+    charm: cs:hacluster
+relations:
+- - keystone:ha
+  - keystone-hacluster:ha
+"#;
+        assert_eq!(verify(bundle), VerificationResult::Pass);
+    }
+
+    #[test]
+    fn it_requires_a_relation_to_a_principal() {
+        let bundle = r#"
+applications:
+  keystone-hacluster:
+    charm: cs:hacluster
+"#;
+        assert_eq!(
+            verify(bundle),
+            VerificationResult::Fail {
+                reason: "keystone-hacluster is not related to a principal by ha".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_checks_the_principal() {
+        let bundle = r#"
+applications:
+  glance:
+    charm: cs:glance
+    num_units: 2
+    options:
+      vip: 10.0.0.11
+  glance-hacluster:
+    charm: cs:hacluster
+    options:
+      cluster_count: 3
+  keystone:
+    charm: cs:keystone
+    num_units: 3
+  keystone-hacluster:
+    charm: cs:hacluster
+relations:
+- - glance
+  - glance-hacluster
+- - keystone:ha
+  - keystone-hacluster:ha
+"#;
+        assert_eq!(
+            verify(bundle),
+            VerificationResult::Fail {
+                reason:
+                    "glance-hacluster / cluster_count is 3, requires 2 to match the units of glance"
+                        .into()
+            }
+        );
+        let bundle = bundle.replace("num_units: 2", "num_units: 3");
+        assert_eq!(
+            verify(&bundle),
+            VerificationResult::Fail {
+                reason: "keystone / vip is unset, required by keystone-hacluster".into()
+            }
+        );
+    }
+}
+
+/// Checks that every application of a subordinate charm is paired with
+/// its principal, e.g. `hacluster` with the API charm it clusters.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Subordinate {
+    /// The subordinate charm name, without schema, series or revision.
+    pub charm: String,
+    /// The subordinate's endpoint for the relation to its principal; any
+    /// relation counts when omitted.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// Options that must be set on the principal.
+    #[serde(default)]
+    pub principal_options: Vec<String>,
+    /// An option on the subordinate that must equal the principal's number
+    /// of units.
+    #[serde(default)]
+    pub units_option: Option<String>,
+    /// The charm's default for `units_option`, used when it is unset.
+    #[serde(default)]
+    pub units_default: Option<u64>,
+}
+
+impl Subordinate {
+    pub fn parse(input: &str) -> Result<Subordinate, Error> {
+        Ok(serde_yaml::from_str(input)?)
+    }
+
+    pub fn verify(&self, bundle: &Bundle) -> VerificationResult {
+        let mut names: Vec<_> = bundle
+            .applications
+            .iter()
+            .filter(|(_, application)| application.charm_name() == self.charm)
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        for name in names {
+            let principals = self.principals(name, bundle);
+            if principals.is_empty() {
+                return VerificationResult::Fail {
+                    reason: match &self.endpoint {
+                        Some(endpoint) => {
+                            format!("{} is not related to a principal by {}", name, endpoint)
+                        }
+                        None => format!("{} is not related to a principal", name),
+                    },
+                };
+            }
+            for principal in principals {
+                if let Some(application) = bundle.application(&principal) {
+                    if let VerificationResult::Fail { reason: f } =
+                        self.verify_principal(name, &principal, application, bundle)
+                    {
+                        return VerificationResult::Fail { reason: f };
+                    }
+                }
+            }
+        }
+        VerificationResult::Pass
+    }
+
+    /// The applications related to the subordinate `name` by its endpoint.
+    fn principals(&self, name: &str, bundle: &Bundle) -> Vec<String> {
+        let end = RelationEndpoint::new(name, self.endpoint.as_deref());
+        let mut principals: Vec<_> = bundle
+            .relation_endpoints()
+            .into_iter()
+            .filter_map(|[a, b]| {
+                if end.matches(&a) {
+                    Some(b.application)
+                } else if end.matches(&b) {
+                    Some(a.application)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        principals.sort();
+        principals.dedup();
+        principals
+    }
+
+    fn verify_principal(
+        &self,
+        name: &str,
+        principal: &str,
+        application: &Application,
+        bundle: &Bundle,
+    ) -> VerificationResult {
+        for option in &self.principal_options {
+            match application.option(option) {
+                None | Some(Value::Null) => {
+                    return VerificationResult::Fail {
+                        reason: format!(
+                            "{} / {} is unset, required by {}",
+                            principal, option, name
+                        ),
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        let units_option = match &self.units_option {
+            Some(units_option) => units_option,
+            None => return VerificationResult::Pass,
+        };
+        let units = Value::from(application.num_units() as u64);
+        let subordinate = bundle.application(name);
+        let value = match subordinate.and_then(|s| s.option(units_option)) {
+            Some(value) => value.clone(),
+            None => match self.units_default {
+                Some(default) => Value::from(default),
+                None => {
+                    return VerificationResult::Fail {
+                        reason: format!(
+                            "{} / {} is unset, requires {} to match the units of {}",
+                            name,
+                            units_option,
+                            display_value(&units),
+                            principal
+                        ),
+                    }
+                }
+            },
+        };
+        if !Comparison::Loose.equal(&value, &units) {
+            return VerificationResult::Fail {
+                reason: format!(
+                    "{} / {} is {}, requires {} to match the units of {}",
+                    name,
+                    units_option,
+                    display_value(&value),
+                    display_value(&units),
+                    principal
+                ),
+            };
+        }
+        VerificationResult::Pass
+    }
+}
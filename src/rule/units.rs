@@ -0,0 +1,168 @@
+use crate::juju::{Application, Bundle};
+use failure::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::rule::VerificationResult;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLE: &str = r#"
+machines:
+  "0": {}
+  "1": {}
+  "2": {}
+applications:
+  ceph-osd:
+    charm: cs:ceph-osd
+    num_units: 3
+    to: ["0", "1", "2"]
+  ceph-mon:
+    charm: cs:ceph-mon
+    num_units: 3
+    to: ["lxd:0", "lxd:1", "lxd:ceph-osd/0"]
+  nova-compute:
+    charm: cs:nova-compute
+    num_units: 2
+"#;
+
+    fn verify(units: &str, name: &str) -> VerificationResult {
+        let bundle = Bundle::load(BUNDLE).unwrap();
+        let application = bundle.application(name).unwrap();
+        Units::parse(units)
+            .unwrap()
+            .verify(name, application, &bundle)
+    }
+
+    #[test]
+    fn it_checks_unit_counts() {
+        assert_eq!(
+            verify("{min: 3, odd: true}", "ceph-osd"),
+            VerificationResult::Pass
+        );
+        assert_eq!(
+            verify("odd: true", "nova-compute"),
+            VerificationResult::Fail {
+                reason: "nova-compute has 2 units, requires an odd number".into()
+            }
+        );
+        assert_eq!(
+            verify("max: 1", "nova-compute"),
+            VerificationResult::Fail {
+                reason: "nova-compute has 2 units, requires at most 1".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_compares_with_another_application() {
+        assert_eq!(
+            verify("equal_to: ceph-osd", "nova-compute"),
+            VerificationResult::Fail {
+                reason: "nova-compute has 2 units, requires 3 like ceph-osd".into()
+            }
+        );
+    }
+
+    #[test]
+    fn it_flags_units_sharing_a_machine() {
+        assert_eq!(
+            verify("separate_machines: true", "ceph-mon"),
+            VerificationResult::Fail {
+                reason: "ceph-mon/0 and ceph-mon/2 are both placed on machine 0".into()
+            }
+        );
+    }
+}
+
+/// Checks on the number of units of an application and where they are
+/// placed.
+#[derive(Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Units {
+    #[serde(default)]
+    pub min: Option<usize>,
+    #[serde(default)]
+    pub max: Option<usize>,
+    /// The number of units must be odd, as quorum based applications need.
+    #[serde(default)]
+    pub odd: bool,
+    /// The number of units must equal that of another application.
+    #[serde(default)]
+    pub equal_to: Option<String>,
+    /// No two units may be placed on the same machine, including in
+    /// containers on it.
+    #[serde(default)]
+    pub separate_machines: bool,
+}
+
+impl Units {
+    pub fn parse(input: &str) -> Result<Units, Error> {
+        Ok(serde_yaml::from_str(input)?)
+    }
+
+    pub fn verify(
+        &self,
+        name: &str,
+        application: &Application,
+        bundle: &Bundle,
+    ) -> VerificationResult {
+        let count = application.num_units();
+        let fail = |requirement: String| VerificationResult::Fail {
+            reason: format!("{} has {} units, requires {}", name, count, requirement),
+        };
+        if let Some(min) = self.min {
+            if count < min {
+                return fail(format!("at least {}", min));
+            }
+        }
+        if let Some(max) = self.max {
+            if count > max {
+                return fail(format!("at most {}", max));
+            }
+        }
+        if self.odd && count % 2 == 0 {
+            return fail("an odd number".to_string());
+        }
+        if let Some(other) = &self.equal_to {
+            match bundle.application(other) {
+                Some(other_application) if other_application.num_units() != count => {
+                    return fail(format!("{} like {}", other_application.num_units(), other));
+                }
+                Some(_) => {}
+                None => {
+                    return VerificationResult::Fail {
+                        reason: format!("{} has {} units, but {} is missing", name, count, other),
+                    }
+                }
+            }
+        }
+        if self.separate_machines {
+            return self.verify_separate(name, application, bundle);
+        }
+        VerificationResult::Pass
+    }
+
+    fn verify_separate(
+        &self,
+        name: &str,
+        application: &Application,
+        bundle: &Bundle,
+    ) -> VerificationResult {
+        let mut hosts = BTreeMap::new();
+        for unit in 0..application.placements().len() {
+            if let Some(machine) = bundle.unit_machine(name, unit) {
+                if let Some(other) = hosts.insert(machine, unit) {
+                    return VerificationResult::Fail {
+                        reason: format!(
+                            "{}/{} and {}/{} are both placed on machine {}",
+                            name, other, name, unit, machine
+                        ),
+                    };
+                }
+            }
+        }
+        VerificationResult::Pass
+    }
+}